@@ -0,0 +1,111 @@
+use crate::value::Value;
+
+/**
+ * Position in the source code, points to the first token of a node.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Span {
+    pub line: u32, pub column: u32
+}
+
+impl Span {
+    pub fn new(line: u32, column: u32) -> Span {
+        Span {
+            line: line,
+            column: column
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stmt {
+    pub kind: StmtKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StmtKind {
+    Let(String, Expr),                       // let <name> = <expr>;
+    LetPattern(Pattern, Expr),               // let [a, ...rest] = <expr>; or let {x, y} = <expr>;
+    Assign(Expr, Expr),                      // <target> = <expr>; target is a Variable, an Index, a Field of any expression or an Array of targets
+    CompoundAssign(Expr, String, Expr),      // <target> <operator>= <expr>; the operator is without the '=' e.g. "+"
+    Function(String, Vec<String>, Vec<Stmt>), // fn <name>(<args>) { <body> }
+    Struct(String, Vec<String>),             // struct <name> { <fields> }
+    Impl(String, Vec<Stmt>),                 // impl <struct name> { <methods> }, the methods are Functions
+    Return(Option<Expr>),
+    If(Expr, Vec<Stmt>, Vec<Stmt>),          // condition, if body, else body ("else if" is an If inside the else body)
+    Match(Expr, Vec<MatchArm>),              // match <expr> { <arms> }, the first matching arm runs
+    While(Expr, Vec<Stmt>),
+    For(Option<Box<Stmt>>, Option<Expr>, Option<Box<Stmt>>, Vec<Stmt>), // for (<init>; <condition>; <step>) { <body> }
+    ForIn(Vec<String>, Expr, Vec<Stmt>),     // for <name>[, <name>] in <expr> { <body> }
+    Break,
+    Continue,
+    Try(Vec<Stmt>, Option<(String, Vec<Stmt>)>, Option<Vec<Stmt>>), // try { <body> } catch (<name>) { <body> } finally { <body> }
+    Throw(Expr),
+    Import(String),
+    Expr(Expr),                              // Expression whose value is discarded, e.g. "foo();"
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExprKind {
+    Value(Value),
+    Variable(String),
+    Array(Vec<Expr>),
+    Map(Vec<(String, Expr)>),                // { "key": <expr>, ... }
+    Index(Box<Expr>, Box<Expr>),             // array, index
+    Call(Box<Expr>, Vec<Expr>),              // function, arguments. Calling a Field calls a method
+    Field(Box<Expr>, String),                // <expr>.<field>
+    StructLiteral(String, Vec<(String, Expr)>), // <struct name> { <field>: <expr>, ... }
+    Function(Vec<String>, Vec<Stmt>),        // fn(<args>) { <body> }
+    Unary(String, Box<Expr>),                // operator, operand. "-x", "!x", "~x"
+    If(Box<Expr>, Box<Expr>, Box<Expr>),     // if <condition> { <expr> } else { <expr> }
+    Binary(Box<Expr>, String, Box<Expr>),    // lhs, operator, rhs
+    Range(Option<Box<Expr>>, Option<Box<Expr>>, bool), // start, end, inclusive. "a..b", "a..=b", both ends are optional "..b", "a.."
+    Interpolation(Vec<Expr>),                // "a ${<expr>} b", the text and the expressions in order, joined as strings
+}
+
+/**
+ * <pattern> if <guard> => <body>
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchArm {
+    pub pattern: Pattern,
+    pub guard: Option<Expr>,
+    pub body: Vec<Stmt>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pattern {
+    Wildcard,                                // _
+    Value(Value),                            // Equal to a literal: 1, "a", true, null
+    Binding(String),                         // Matches anything and binds it to a new variable
+    Array(Vec<Pattern>),                     // [<patterns>], an array with exactly as many elements, unless the last one is a Rest
+    Rest(Option<String>),                    // ...<name>, binds the remaining elements of an array to a new array, "..._" ignores them
+    Fields(Vec<String>),                     // {<fields>}, a struct with these fields, binds them to variables with the same names
+    Or(Vec<Pattern>),                        // <pattern> | <pattern>, the alternatives can't bind variables
+}
+
+impl Stmt {
+    pub fn new(kind: StmtKind, span: Span) -> Stmt {
+        Stmt {
+            kind: kind,
+            span: span
+        }
+    }
+}
+
+impl Expr {
+    pub fn new(kind: ExprKind, span: Span) -> Expr {
+        Expr {
+            kind: kind,
+            span: span
+        }
+    }
+}
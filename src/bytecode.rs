@@ -0,0 +1,112 @@
+use crate::ast::Span;
+use crate::value::Value;
+use std::rc::Rc;
+
+/**
+ * Instructions of the virtual machine, operands are indices into the chunk or slots.
+ * Locals live in the value stack starting from the call frame's base,
+ * globals are resolved by the compiler into slots of Interpreter::globals.
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OpCode {
+    Constant(u32),         // Pushes a constant from the constant pool
+    Pop,
+    Dup(u32),              // Pushes copies of the top n values, in the same order
+    GetLocal(u32),         // Pushes a copy of the local slot
+    SetLocal(u32),         // Pops a value and stores it into the local slot
+    DefineGlobal(u32),     // Pops a value and declares the global slot with it
+    GetGlobal(u32),
+    SetGlobal(u32),
+    GetUpvalue(u32),       // Index into the closure's captured variables
+    SetUpvalue(u32),
+    CloseUpvalue,          // Pops a captured local, moving it into its upvalue
+    MakeArray(u32),        // How many values to pop from the stack to create the array
+    MakeMap(u32),          // How many key, value pairs to pop from the stack to create the map
+    MakeObject(u32),       // Pops the field name, value pairs and the struct, pushes the new object
+    MakeRange(bool),       // Inclusive. Pops the end and the start, missing ones are Null
+    Interpolate(u32),      // How many values to pop, pushes them joined into one string
+    GetIndex,              // Pops the index and the array (or map), pushes the element
+    SetIndex,              // Pops the value, the index and the array (or map), modifies the array in place
+    GetField(u32),         // Field name constant. Pops the object, pushes the field's value
+    SetField(u32),         // Pops the value and the object, modifies the object in place
+    Negate,                // Unary operators pop one value and push the result
+    Not,
+    BitNot,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Modulo,
+    Power,
+    Less,
+    Greater,
+    LessEqual,
+    GreaterEqual,
+    Equal,
+    NotEqual,
+    BitAnd,
+    BitOr,
+    BitXor,
+    ShiftLeft,
+    ShiftRight,
+    Jump(u32),             // Absolute position in the chunk
+    JumpIfFalse(u32),      // Pops the condition
+    JumpIfFalseOrPop(u32), // Keeps the condition when jumping, used by "&&" and "||"
+    JumpIfTrueOrPop(u32),
+    MatchArray(u32, bool), // Element count, whether there can be more. Pops a value, pushes whether it's an array with that many elements
+    CheckArray(u32, bool), // Same as MatchArray, but raises an error instead of pushing false, used by destructuring
+    MatchFields(u32),      // Field names constant (an Array). Pops a value, pushes whether it's an object with these fields
+    IterNext(u32, u32),    // Jump target, variable count. Pushes the next item of the iterable and advances the position and offset below it, jumps when done
+    PushHandler(u32),      // Catch position. Errors jump there with the stack cut back and the error value pushed
+    PopHandler,
+    Throw,                 // Pops the value and raises it as an error
+    Call(u32),             // Argument count. The function is pushed first, then the arguments
+    Invoke(u32, u32),      // Method name constant, argument count. Like Call, but with the object instead of the function
+    Return,                // Pops the return value and leaves the call frame
+    Closure(u32),          // Index into Chunk::functions, captures the upvalues and pushes the function
+    Struct(u32, u32),      // Struct name constant, field names constant (an Array). Pushes the new struct type
+    Method(u32),           // Method name constant. Pops a function and adds it to the struct below it
+    Import(u32),           // Library name constant
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Chunk {
+    pub code: Vec<OpCode>,
+    pub spans: Vec<Span>, // Source position of every instruction, used for errors
+    pub constants: Vec<Value>,
+    pub functions: Vec<Rc<FunctionProto>>,
+}
+
+/**
+ * Compiled function, the whole script is compiled into one as well.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionProto {
+    pub name: String,
+    pub arity: u32,
+    pub chunk: Chunk,
+    pub upvalues: Vec<UpvalueRef>, // Variables captured when the closure is created
+}
+
+/**
+ * Where a captured variable comes from, either a local of the enclosing function
+ * or one of the enclosing function's own upvalues.
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UpvalueRef {
+    pub is_local: bool,
+    pub index: u32,
+}
+
+impl Chunk {
+    pub fn emit(&mut self, op: OpCode, span: Span) -> usize {
+        self.code.push(op);
+        self.spans.push(span);
+        self.code.len() - 1
+    }
+
+    pub fn add_constant(&mut self, value: Value) -> u32 {
+        self.constants.push(value);
+        (self.constants.len() - 1) as u32
+    }
+}
@@ -0,0 +1,1153 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::ast::{Stmt, StmtKind, Expr, ExprKind, Pattern, Span};
+use crate::bytecode::{OpCode, Chunk, FunctionProto, UpvalueRef};
+use crate::error::{GiffiError, ErrorKind};
+use crate::value::{Value, Range};
+
+struct Local {
+    name: String,
+    depth: u32,
+    captured: bool, // Captured by a closure, closed instead of popped when going out of scope
+}
+
+/**
+ * One step from a destructured value to a part of it.
+ */
+#[derive(Clone)]
+enum PathStep {
+    Index(i64),    // value[i]
+    Rest(i64),     // value[i..]
+    Field(String), // value.field
+}
+
+/**
+ * Try (or catch) block being compiled, early exits out of it have to pop its handler and run its finally block.
+ */
+#[derive(Clone)]
+struct TryBlock {
+    loops: usize,               // How many loops are around it, "break" and "continue" of the inner ones stay in the block
+    finally: Option<Vec<Stmt>>,
+}
+
+struct Loop {
+    break_depth: u32,     // Locals deeper than this are popped by "break"
+    continue_depth: u32,  // Locals deeper than this are popped by "continue"
+    breaks: Vec<usize>,   // "break" jumps, patched to the end of the loop
+    continues: Vec<usize> // "continue" jumps, patched to the end of the body
+}
+
+/**
+ * Everything the compiler tracks about the function being compiled.
+ */
+struct FunctionState {
+    function: FunctionProto,
+    locals: Vec<Local>,
+    loops: Vec<Loop>,
+    tries: Vec<TryBlock>,
+    scope_depth: u32,
+    is_script: bool,
+}
+
+/**
+ * Lowers the statements into bytecode.
+ * Variables declared in the top level of a script are globals, everything else is a local slot.
+ * Locals of enclosing functions are captured as upvalues.
+ */
+pub struct Compiler<'a> {
+    state: FunctionState,
+    enclosing: Vec<FunctionState>, // Functions the current one is nested in, innermost last
+    global_names: &'a mut HashMap<String, usize>,
+    span: Span,
+}
+
+impl<'a> Compiler<'a> {
+    /**
+     * Global names are shared between the compiled scripts (REPL lines, imports),
+     * new ones get added to the map.
+     */
+    pub fn compile(statements: &Vec<Stmt>, global_names: &'a mut HashMap<String, usize>) -> Result<Rc<FunctionProto>, GiffiError> {
+        let mut compiler = Compiler {
+            state: FunctionState::new("script".to_string(), 0, true),
+            enclosing: vec![],
+            global_names: global_names,
+            span: Span::default(),
+        };
+        compiler.compile_body(statements)?;
+        compiler.emit_return_null();
+        Ok(Rc::new(compiler.state.function))
+    }
+
+    fn compile_body(&mut self, statements: &Vec<Stmt>) -> Result<(), GiffiError> {
+        for statement in statements {
+            self.compile_statement(statement)?;
+        }
+        Ok(())
+    }
+
+    fn compile_block(&mut self, statements: &Vec<Stmt>) -> Result<(), GiffiError> {
+        self.begin_scope();
+        self.compile_body(statements)?;
+        self.end_scope();
+        Ok(())
+    }
+
+    fn compile_statement(&mut self, statement: &Stmt) -> Result<(), GiffiError> {
+        self.span = statement.span;
+
+        match &statement.kind {
+            StmtKind::Let(var_name, expr) => {
+                self.compile_expr(expr)?;
+                self.span = statement.span;
+                if self.state.is_script && self.state.scope_depth == 0 {
+                    let slot = self.global_slot(var_name);
+                    self.emit(OpCode::DefineGlobal(slot));
+                }
+                else {
+                    self.declare_local(var_name)?;
+                }
+            }
+            StmtKind::LetPattern(pattern, expr) => {
+                self.compile_expr(expr)?;
+                self.span = statement.span;
+                self.compile_let_pattern(pattern)?;
+            }
+            StmtKind::Assign(target, expr) => {
+                self.compile_assignment(target, expr)?;
+            }
+            StmtKind::CompoundAssign(target, op, expr) => {
+                self.compile_compound_assignment(target, op, expr)?;
+            }
+            StmtKind::Function(fn_name, args, body) => {
+                if self.state.is_script && self.state.scope_depth == 0 {
+                    self.compile_function(fn_name, args, body)?;
+                    let slot = self.global_slot(fn_name);
+                    self.emit(OpCode::DefineGlobal(slot));
+                }
+                else {
+                    // Declared before the body is compiled so the function can call itself
+                    self.declare_local(fn_name)?;
+                    self.compile_function(fn_name, args, body)?;
+                }
+            }
+            StmtKind::Struct(struct_name, fields) => {
+                let name = self.state.function.chunk.add_constant(Value::Literal(struct_name.clone()));
+                let field_names = fields.iter().map(|field| Value::Literal(field.clone())).collect();
+                let field_names = self.state.function.chunk.add_constant(Value::array(field_names));
+                self.emit(OpCode::Struct(name, field_names));
+                if self.state.is_script && self.state.scope_depth == 0 {
+                    let slot = self.global_slot(struct_name);
+                    self.emit(OpCode::DefineGlobal(slot));
+                }
+                else {
+                    self.declare_local(struct_name)?;
+                }
+            }
+            StmtKind::Impl(struct_name, methods) => {
+                self.get_variable(struct_name);
+                for method in methods {
+                    if let StmtKind::Function(fn_name, args, body) = &method.kind {
+                        self.span = method.span;
+                        self.compile_function(fn_name, args, body)?;
+                        let name = self.state.function.chunk.add_constant(Value::Literal(fn_name.clone()));
+                        self.emit(OpCode::Method(name));
+                    }
+                }
+                self.emit(OpCode::Pop);
+            }
+            StmtKind::Return(expr) => {
+                if self.state.is_script {
+                    return Err(self.error("'return' outside of a function".to_string()));
+                }
+                match expr {
+                    Some(expr) => { self.compile_expr(expr)?; }
+                    None => { self.emit_constant(Value::Null); }
+                }
+                if self.state.tries.len() > 0 {
+                    // The return value waits below the finally blocks
+                    self.begin_scope();
+                    self.declare_local(&" return".to_string())?;
+                    self.exit_tries(0)?;
+                    self.forget_scope();
+                }
+                self.emit(OpCode::Return);
+            }
+            StmtKind::If(check, true_body, false_body) => {
+                self.compile_expr(check)?;
+                let jump_to_else = self.emit(OpCode::JumpIfFalse(0));
+                self.compile_block(true_body)?;
+
+                if false_body.len() > 0 {
+                    let jump_to_end = self.emit(OpCode::Jump(0));
+                    self.patch_jump(jump_to_else);
+                    self.compile_block(false_body)?;
+                    self.patch_jump(jump_to_end);
+                }
+                else {
+                    self.patch_jump(jump_to_else);
+                }
+            }
+            StmtKind::Match(value, arms) => {
+                // The matched value is a hidden local, patterns index into it
+                self.begin_scope();
+                self.compile_expr(value)?;
+                self.declare_local(&" match".to_string())?;
+                let slot = (self.state.locals.len() - 1) as u32;
+
+                let mut jumps_to_end = vec![];
+                for arm in arms {
+                    self.span = arm.span;
+                    let mut jumps_to_next_arm = vec![];
+                    self.compile_pattern_test(&arm.pattern, slot, &mut vec![], &mut jumps_to_next_arm)?;
+
+                    self.begin_scope();
+                    self.compile_pattern_bindings(&arm.pattern, slot)?;
+                    if let Some(guard) = &arm.guard {
+                        self.compile_expr(guard)?;
+                        let guard_failed = self.emit(OpCode::JumpIfFalse(0));
+                        self.compile_block(&arm.body)?;
+                        self.pop_locals_deeper_than(self.state.scope_depth - 1);
+                        jumps_to_end.push(self.emit(OpCode::Jump(0)));
+
+                        // The bindings are popped on both paths
+                        self.patch_jump(guard_failed);
+                        self.end_scope();
+                    }
+                    else {
+                        self.compile_block(&arm.body)?;
+                        self.end_scope();
+                        jumps_to_end.push(self.emit(OpCode::Jump(0)));
+                    }
+                    self.patch_jumps(jumps_to_next_arm);
+                }
+                self.patch_jumps(jumps_to_end);
+                self.end_scope();
+            }
+            StmtKind::While(check, body) => {
+                let start = self.state.function.chunk.code.len();
+                self.compile_expr(check)?;
+                let jump_to_end = self.emit(OpCode::JumpIfFalse(0));
+
+                self.begin_loop(self.state.scope_depth, self.state.scope_depth);
+                self.compile_block(body)?;
+                let lp = self.state.loops.pop().unwrap();
+                self.patch_jumps(lp.continues);
+                self.emit(OpCode::Jump(start as u32));
+
+                self.patch_jump(jump_to_end);
+                self.patch_jumps(lp.breaks);
+            }
+            StmtKind::For(init, check, step, body) => {
+                self.begin_scope();
+                let mut loop_variable = None;
+                if let Some(init) = init {
+                    if let StmtKind::Let(var_name, _) = &init.kind {
+                        loop_variable = Some(var_name.clone());
+                    }
+                    self.compile_statement(init)?;
+                }
+
+                let start = self.state.function.chunk.code.len();
+                let mut jump_to_end = None;
+                if let Some(check) = check {
+                    self.compile_expr(check)?;
+                    jump_to_end = Some(self.emit(OpCode::JumpIfFalse(0)));
+                }
+
+                // Every iteration gets its own copy of the loop variable, closures in the body capture the copy.
+                // The copy is written back before the step.
+                self.begin_scope();
+                let mut outer_slot = None;
+                if let Some(var_name) = &loop_variable {
+                    outer_slot = self.state.resolve_local(var_name);
+                    self.get_variable(var_name);
+                    self.declare_local(var_name)?;
+                }
+                self.begin_loop(self.state.scope_depth - 1, self.state.scope_depth);
+                self.compile_block(body)?;
+                let lp = self.state.loops.pop().unwrap();
+                self.patch_jumps(lp.continues);
+                if let (Some(var_name), Some(slot)) = (&loop_variable, outer_slot) {
+                    self.get_variable(var_name);
+                    self.emit(OpCode::SetLocal(slot));
+                }
+                self.end_scope();
+
+                if let Some(step) = step {
+                    self.compile_statement(step)?;
+                }
+                self.emit(OpCode::Jump(start as u32));
+
+                if let Some(jump_to_end) = jump_to_end {
+                    self.patch_jump(jump_to_end);
+                }
+                self.patch_jumps(lp.breaks);
+                self.end_scope();
+            }
+            StmtKind::ForIn(var_names, iterable, body) => {
+                // The iterable, the position in it and the byte offset of strings are hidden locals, spaces keep them out of reach of the code
+                self.begin_scope();
+                self.compile_expr(iterable)?;
+                self.declare_local(&" iterable".to_string())?;
+                self.emit_constant(Value::Int(0));
+                self.declare_local(&" position".to_string())?;
+                self.emit_constant(Value::Int(0));
+                self.declare_local(&" offset".to_string())?;
+
+                self.span = statement.span;
+                let start = self.state.function.chunk.code.len();
+                let jump_to_end = self.emit(OpCode::IterNext(0, var_names.len() as u32));
+
+                self.begin_scope();
+                for var_name in var_names {
+                    self.declare_local(var_name)?;
+                }
+                self.begin_loop(self.state.scope_depth - 1, self.state.scope_depth - 1);
+                self.compile_block(body)?;
+                let lp = self.state.loops.pop().unwrap();
+                self.end_scope();
+                self.patch_jumps(lp.continues);
+                self.emit(OpCode::Jump(start as u32));
+
+                self.patch_jump(jump_to_end);
+                self.patch_jumps(lp.breaks);
+                self.end_scope();
+            }
+            StmtKind::Break => {
+                if self.state.loops.len() == 0 {
+                    return Err(self.error("'break' outside of a loop".to_string()));
+                }
+                self.exit_loop_tries()?;
+                self.pop_locals_deeper_than(self.state.loops.last().unwrap().break_depth);
+                let jump = self.emit(OpCode::Jump(0));
+                self.state.loops.last_mut().unwrap().breaks.push(jump);
+            }
+            StmtKind::Continue => {
+                if self.state.loops.len() == 0 {
+                    return Err(self.error("'continue' outside of a loop".to_string()));
+                }
+                self.exit_loop_tries()?;
+                self.pop_locals_deeper_than(self.state.loops.last().unwrap().continue_depth);
+                let jump = self.emit(OpCode::Jump(0));
+                self.state.loops.last_mut().unwrap().continues.push(jump);
+            }
+            StmtKind::Try(body, catch, finally) => {
+                self.compile_try(body, catch, finally)?;
+            }
+            StmtKind::Throw(expr) => {
+                self.compile_expr(expr)?;
+                self.span = statement.span;
+                self.emit(OpCode::Throw);
+            }
+            StmtKind::Import(library) => {
+                let idx = self.state.function.chunk.add_constant(Value::Literal(library.clone()));
+                self.emit(OpCode::Import(idx));
+            }
+            StmtKind::Expr(expr) => {
+                self.compile_expr(expr)?;
+                self.emit(OpCode::Pop);
+            }
+        }
+        Ok(())
+    }
+
+    fn compile_expr(&mut self, expr: &Expr) -> Result<(), GiffiError> {
+        self.span = expr.span;
+
+        match &expr.kind {
+            ExprKind::Value(v) => {
+                self.emit_constant(v.clone());
+            }
+            ExprKind::Variable(var_name) => {
+                self.get_variable(var_name);
+            }
+            ExprKind::Array(elements) => {
+                for element in elements {
+                    self.compile_expr(element)?;
+                }
+                self.span = expr.span;
+                self.emit(OpCode::MakeArray(elements.len() as u32));
+            }
+            ExprKind::Map(entries) => {
+                for (key, value) in entries {
+                    self.emit_constant(Value::Literal(key.clone()));
+                    self.compile_expr(value)?;
+                }
+                self.span = expr.span;
+                self.emit(OpCode::MakeMap(entries.len() as u32));
+            }
+            ExprKind::Index(array, index) => {
+                self.compile_expr(array)?;
+                self.compile_expr(index)?;
+                self.span = expr.span;
+                self.emit(OpCode::GetIndex);
+            }
+            ExprKind::Call(function, args) => {
+                // "object.method(args)"
+                if let ExprKind::Field(object, method) = &function.kind {
+                    self.compile_expr(object)?;
+                    for arg in args {
+                        self.compile_expr(arg)?;
+                    }
+                    self.span = expr.span;
+                    let name = self.state.function.chunk.add_constant(Value::Literal(method.clone()));
+                    self.emit(OpCode::Invoke(name, args.len() as u32));
+                    return Ok(());
+                }
+
+                self.compile_expr(function)?;
+                for arg in args {
+                    self.compile_expr(arg)?;
+                }
+                self.span = expr.span;
+                self.emit(OpCode::Call(args.len() as u32));
+            }
+            ExprKind::Field(object, field) => {
+                self.compile_expr(object)?;
+                self.span = expr.span;
+                let name = self.state.function.chunk.add_constant(Value::Literal(field.clone()));
+                self.emit(OpCode::GetField(name));
+            }
+            ExprKind::StructLiteral(struct_name, fields) => {
+                self.get_variable(struct_name);
+                for (field, value) in fields {
+                    self.emit_constant(Value::Literal(field.clone()));
+                    self.compile_expr(value)?;
+                }
+                self.span = expr.span;
+                self.emit(OpCode::MakeObject(fields.len() as u32));
+            }
+            ExprKind::Function(args, body) => {
+                self.compile_function(&"fn".to_string(), args, body)?;
+            }
+            ExprKind::Unary(op, operand) => {
+                // Negative literals are folded into constants, ones which would overflow are left to Negate to report
+                if op == "-" {
+                    if let ExprKind::Value(Value::Int(i)) = &operand.kind {
+                        if let Some(negated) = i.checked_neg() {
+                            self.emit_constant(Value::Int(negated));
+                            return Ok(());
+                        }
+                    }
+                    if let ExprKind::Value(Value::Float(f)) = &operand.kind {
+                        self.emit_constant(Value::Float(-f));
+                        return Ok(());
+                    }
+                }
+
+                self.compile_expr(operand)?;
+                self.span = expr.span;
+                let opcode = match op.as_str() {
+                    "-" => OpCode::Negate,
+                    "!" => OpCode::Not,
+                    "~" => OpCode::BitNot,
+                    _ => { return Err(self.error(format!("Unimplumented operator {}", op))); }
+                };
+                self.emit(opcode);
+            }
+            ExprKind::If(check, then, otherwise) => {
+                self.compile_expr(check)?;
+                let jump_to_else = self.emit(OpCode::JumpIfFalse(0));
+                self.compile_expr(then)?;
+                let jump_to_end = self.emit(OpCode::Jump(0));
+                self.patch_jump(jump_to_else);
+                self.compile_expr(otherwise)?;
+                self.patch_jump(jump_to_end);
+            }
+            ExprKind::Range(start, end, inclusive) => {
+                for bound in [start, end] {
+                    match bound {
+                        Some(bound) => { self.compile_expr(bound)?; }
+                        None => { self.emit_constant(Value::Null); }
+                    }
+                }
+                self.span = expr.span;
+                self.emit(OpCode::MakeRange(*inclusive));
+            }
+            ExprKind::Interpolation(parts) => {
+                for part in parts {
+                    self.compile_expr(part)?;
+                }
+                self.span = expr.span;
+                self.emit(OpCode::Interpolate(parts.len() as u32));
+            }
+            ExprKind::Binary(lhs, op, rhs) if op == "&&" || op == "||" => {
+                // Short circuits, the value is the operand which decided the result "a || default"
+                self.compile_expr(lhs)?;
+                self.span = expr.span;
+                let jump = if op == "&&" {
+                    self.emit(OpCode::JumpIfFalseOrPop(0))
+                }
+                else {
+                    self.emit(OpCode::JumpIfTrueOrPop(0))
+                };
+                self.compile_expr(rhs)?;
+                self.patch_jump(jump);
+            }
+            ExprKind::Binary(lhs, op, rhs) => {
+                self.compile_expr(lhs)?;
+                self.compile_expr(rhs)?;
+                self.span = expr.span;
+                let opcode = self.binary_opcode(op)?;
+                self.emit(opcode);
+            }
+        }
+        Ok(())
+    }
+
+    fn binary_opcode(&self, op: &String) -> Result<OpCode, GiffiError> {
+        let opcode = match op.as_str() {
+            "+" => OpCode::Add,
+            "-" => OpCode::Sub,
+            "*" => OpCode::Mul,
+            "/" => OpCode::Div,
+            "%" => OpCode::Modulo,
+            "**" => OpCode::Power,
+            "<" => OpCode::Less,
+            ">" => OpCode::Greater,
+            "<=" => OpCode::LessEqual,
+            ">=" => OpCode::GreaterEqual,
+            "==" => OpCode::Equal,
+            "!=" => OpCode::NotEqual,
+            "&" => OpCode::BitAnd,
+            "|" => OpCode::BitOr,
+            "^" => OpCode::BitXor,
+            "<<" => OpCode::ShiftLeft,
+            ">>" => OpCode::ShiftRight,
+            _ => { return Err(self.error(format!("Unimplumented operator {}", op))); }
+        };
+        Ok(opcode)
+    }
+
+    fn compile_assignment(&mut self, target: &Expr, value: &Expr) -> Result<(), GiffiError> {
+        match &target.kind {
+            ExprKind::Variable(var_name) => {
+                self.compile_expr(value)?;
+                self.span = target.span;
+                self.set_variable(var_name);
+                return Ok(());
+            }
+            ExprKind::Index(array, index) => {
+                // The array is shared, so it's modified in place
+                self.compile_expr(array)?;
+                self.compile_expr(index)?;
+                self.compile_expr(value)?;
+                self.span = target.span;
+                self.emit(OpCode::SetIndex);
+                return Ok(());
+            }
+            ExprKind::Field(object, field) => {
+                self.compile_expr(object)?;
+                self.compile_expr(value)?;
+                self.span = target.span;
+                let name = self.state.function.chunk.add_constant(Value::Literal(field.clone()));
+                self.emit(OpCode::SetField(name));
+                return Ok(());
+            }
+            ExprKind::Array(targets) => {
+                // "[a, b] = [b, a];" the value is kept in a hidden local while its elements are assigned one by one
+                self.compile_expr(value)?;
+                self.begin_scope();
+                let hidden = " destructure".to_string();
+                self.declare_local(&hidden)?;
+                let slot = (self.state.locals.len() - 1) as u32;
+                self.emit(OpCode::GetLocal(slot));
+                self.emit(OpCode::CheckArray(targets.len() as u32, false));
+                for (idx, element) in targets.iter().enumerate() {
+                    let array = Expr::new(ExprKind::Variable(hidden.clone()), element.span);
+                    let index = Expr::new(ExprKind::Value(Value::Int(idx as i64)), element.span);
+                    self.compile_assignment(element, &Expr::new(ExprKind::Index(Box::new(array), Box::new(index)), element.span))?;
+                }
+                self.end_scope();
+                return Ok(());
+            }
+            _ => {
+                return Err(self.error(format!("Cannot assign into {:?}", target.kind)));
+            }
+        }
+    }
+
+    /**
+     * "target op= value", the parts of the target are only evaluated once, "arr[next()] += 1"
+     */
+    fn compile_compound_assignment(&mut self, target: &Expr, op: &String, value: &Expr) -> Result<(), GiffiError> {
+        let opcode = self.binary_opcode(op)?;
+        match &target.kind {
+            ExprKind::Variable(var_name) => {
+                self.get_variable(var_name);
+                self.compile_expr(value)?;
+                self.span = target.span;
+                self.emit(opcode);
+                self.set_variable(var_name);
+            }
+            ExprKind::Index(array, index) => {
+                // array, index, array[index] <op> value
+                self.compile_expr(array)?;
+                self.compile_expr(index)?;
+                self.span = target.span;
+                self.emit(OpCode::Dup(2));
+                self.emit(OpCode::GetIndex);
+                self.compile_expr(value)?;
+                self.span = target.span;
+                self.emit(opcode);
+                self.emit(OpCode::SetIndex);
+            }
+            ExprKind::Field(object, field) => {
+                self.compile_expr(object)?;
+                self.span = target.span;
+                let name = self.state.function.chunk.add_constant(Value::Literal(field.clone()));
+                self.emit(OpCode::Dup(1));
+                self.emit(OpCode::GetField(name));
+                self.compile_expr(value)?;
+                self.span = target.span;
+                self.emit(opcode);
+                self.emit(OpCode::SetField(name));
+            }
+            _ => {
+                return Err(self.error(format!("Cannot assign into {:?}", target.kind)));
+            }
+        }
+        Ok(())
+    }
+
+    /**
+     * Emits the checks of the pattern against the value at "path" inside the local at "slot",
+     * every check jumps to "fails" if it doesn't match. Nothing is left on the stack.
+     */
+    fn compile_pattern_test(&mut self, pattern: &Pattern, slot: u32, path: &mut Vec<PathStep>, fails: &mut Vec<usize>) -> Result<(), GiffiError> {
+        match pattern {
+            Pattern::Wildcard | Pattern::Binding(_) | Pattern::Rest(_) => {}
+            Pattern::Value(value) => {
+                self.load_pattern_path(slot, path);
+                self.emit_constant(value.clone());
+                self.emit(OpCode::Equal);
+                fails.push(self.emit(OpCode::JumpIfFalse(0)));
+            }
+            Pattern::Array(elements) => {
+                let has_rest = matches!(elements.last(), Some(Pattern::Rest(_)));
+                let count = if has_rest { elements.len() - 1 } else { elements.len() };
+                self.load_pattern_path(slot, path);
+                self.emit(OpCode::MatchArray(count as u32, has_rest));
+                fails.push(self.emit(OpCode::JumpIfFalse(0)));
+                for (idx, element) in elements.iter().take(count).enumerate() {
+                    path.push(PathStep::Index(idx as i64));
+                    self.compile_pattern_test(element, slot, path, fails)?;
+                    path.pop();
+                }
+            }
+            Pattern::Fields(fields) => {
+                let field_names = fields.iter().map(|field| Value::Literal(field.clone())).collect();
+                let field_names = self.state.function.chunk.add_constant(Value::array(field_names));
+                self.load_pattern_path(slot, path);
+                self.emit(OpCode::MatchFields(field_names));
+                fails.push(self.emit(OpCode::JumpIfFalse(0)));
+            }
+            Pattern::Or(alternatives) => {
+                let mut matched = vec![];
+                for (idx, alternative) in alternatives.iter().enumerate() {
+                    let mut bindings = vec![];
+                    Compiler::pattern_bindings(alternative, &mut vec![], &mut bindings);
+                    if bindings.len() > 0 {
+                        return Err(self.error("Alternatives in a pattern can't bind variables".to_string()));
+                    }
+                    if idx == alternatives.len() - 1 {
+                        self.compile_pattern_test(alternative, slot, path, fails)?;
+                        break;
+                    }
+                    let mut alternative_fails = vec![];
+                    self.compile_pattern_test(alternative, slot, path, &mut alternative_fails)?;
+                    matched.push(self.emit(OpCode::Jump(0)));
+                    self.patch_jumps(alternative_fails);
+                }
+                self.patch_jumps(matched);
+            }
+        }
+        Ok(())
+    }
+
+    /**
+     * Declares a local for every binding of an already matched pattern.
+     */
+    fn compile_pattern_bindings(&mut self, pattern: &Pattern, slot: u32) -> Result<(), GiffiError> {
+        let mut bindings = vec![];
+        Compiler::pattern_bindings(pattern, &mut vec![], &mut bindings);
+        for (var_name, path) in bindings {
+            self.load_pattern_path(slot, &path);
+            self.declare_local(&var_name)?;
+        }
+        Ok(())
+    }
+
+    /**
+     * "let [a, ...rest] = value;", the value is expected to be on the top of the stack.
+     * Globals are defined from copies of it, locals need it to stay below them as a hidden local.
+     * Array patterns must have the length of the array, as in a match, unless they have a rest.
+     */
+    fn compile_let_pattern(&mut self, pattern: &Pattern) -> Result<(), GiffiError> {
+        let mut bindings = vec![];
+        Compiler::pattern_bindings(pattern, &mut vec![], &mut bindings);
+        let mut arrays = vec![];
+        Compiler::pattern_arrays(pattern, &mut vec![], &mut arrays);
+        if self.state.is_script && self.state.scope_depth == 0 {
+            for (count, has_rest, path) in arrays {
+                self.emit(OpCode::Dup(1));
+                self.emit_path(&path);
+                self.emit(OpCode::CheckArray(count, has_rest));
+            }
+            for (var_name, path) in bindings {
+                self.emit(OpCode::Dup(1));
+                self.emit_path(&path);
+                let slot = self.global_slot(&var_name);
+                self.emit(OpCode::DefineGlobal(slot));
+            }
+            self.emit(OpCode::Pop);
+            return Ok(());
+        }
+
+        self.declare_local(&format!(" destructure{}", self.state.locals.len()))?;
+        let slot = (self.state.locals.len() - 1) as u32;
+        for (count, has_rest, path) in arrays {
+            self.load_pattern_path(slot, &path);
+            self.emit(OpCode::CheckArray(count, has_rest));
+        }
+        for (var_name, path) in bindings {
+            self.load_pattern_path(slot, &path);
+            self.declare_local(&var_name)?;
+        }
+        Ok(())
+    }
+
+    /**
+     * Element count, whether there's a rest and path of every array pattern, outer ones first.
+     */
+    fn pattern_arrays(pattern: &Pattern, path: &mut Vec<PathStep>, arrays: &mut Vec<(u32, bool, Vec<PathStep>)>) {
+        if let Pattern::Array(elements) = pattern {
+            let has_rest = matches!(elements.last(), Some(Pattern::Rest(_)));
+            let count = if has_rest { elements.len() - 1 } else { elements.len() };
+            arrays.push((count as u32, has_rest, path.clone()));
+            for (idx, element) in elements.iter().take(count).enumerate() {
+                path.push(PathStep::Index(idx as i64));
+                Compiler::pattern_arrays(element, path, arrays);
+                path.pop();
+            }
+        }
+    }
+
+    /**
+     * Variables bound by the pattern and where their values are inside the matched value.
+     */
+    fn pattern_bindings(pattern: &Pattern, path: &mut Vec<PathStep>, bindings: &mut Vec<(String, Vec<PathStep>)>) {
+        match pattern {
+            Pattern::Binding(var_name) | Pattern::Rest(Some(var_name)) => {
+                bindings.push((var_name.clone(), path.clone()));
+            }
+            Pattern::Array(elements) => {
+                for (idx, element) in elements.iter().enumerate() {
+                    let step = if let Pattern::Rest(_) = element { PathStep::Rest(idx as i64) } else { PathStep::Index(idx as i64) };
+                    path.push(step);
+                    Compiler::pattern_bindings(element, path, bindings);
+                    path.pop();
+                }
+            }
+            Pattern::Fields(fields) => {
+                for field in fields {
+                    let mut field_path = path.clone();
+                    field_path.push(PathStep::Field(field.clone()));
+                    bindings.push((field.clone(), field_path));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn load_pattern_path(&mut self, slot: u32, path: &Vec<PathStep>) {
+        self.emit(OpCode::GetLocal(slot));
+        self.emit_path(path);
+    }
+
+    /**
+     * Replaces the value on the top of the stack with the part of it at the end of the path.
+     */
+    fn emit_path(&mut self, path: &Vec<PathStep>) {
+        for step in path {
+            match step {
+                PathStep::Index(idx) => {
+                    self.emit_constant(Value::Int(*idx));
+                    self.emit(OpCode::GetIndex);
+                }
+                PathStep::Rest(idx) => {
+                    self.emit_constant(Value::Range(Box::new(Range { start: Some(*idx), end: None, inclusive: false })));
+                    self.emit(OpCode::GetIndex);
+                }
+                PathStep::Field(field) => {
+                    let name = self.state.function.chunk.add_constant(Value::Literal(field.clone()));
+                    self.emit(OpCode::GetField(name));
+                }
+            }
+        }
+    }
+
+    /**
+     * The handler of the try block jumps to the catch with the error on the stack.
+     * Errors which aren't caught (or happen in the catch block) run the finally block and are thrown again.
+     */
+    fn compile_try(&mut self, body: &Vec<Stmt>, catch: &Option<(String, Vec<Stmt>)>, finally: &Option<Vec<Stmt>>) -> Result<(), GiffiError> {
+        let try_block = TryBlock { loops: self.state.loops.len(), finally: finally.clone() };
+        let handler = self.emit(OpCode::PushHandler(0));
+        self.state.tries.push(try_block.clone());
+        self.compile_block(body)?;
+        self.state.tries.pop();
+        self.emit(OpCode::PopHandler);
+        let mut jumps_to_finally = vec![self.emit(OpCode::Jump(0))];
+        self.patch_jump(handler);
+
+        if let Some((var_name, catch_body)) = catch {
+            self.begin_scope();
+            self.declare_local(var_name)?;
+            if finally.is_none() {
+                self.compile_block(catch_body)?;
+                self.end_scope();
+            }
+            else {
+                let slot = (self.state.locals.len() - 1) as u32;
+                let catch_handler = self.emit(OpCode::PushHandler(0));
+                self.state.tries.push(try_block);
+                self.compile_block(catch_body)?;
+                self.state.tries.pop();
+                self.emit(OpCode::PopHandler);
+                self.end_scope();
+                jumps_to_finally.push(self.emit(OpCode::Jump(0)));
+
+                // The new error replaces the caught one
+                self.patch_jump(catch_handler);
+                self.emit(OpCode::SetLocal(slot));
+            }
+        }
+
+        if let Some(finally) = finally {
+            // The error waits below the finally block to be thrown again
+            self.begin_scope();
+            self.declare_local(&" error".to_string())?;
+            self.compile_block(finally)?;
+            self.emit(OpCode::Throw);
+            self.forget_scope();
+        }
+
+        self.patch_jumps(jumps_to_finally);
+        if let Some(finally) = finally {
+            self.compile_block(finally)?;
+        }
+        Ok(())
+    }
+
+    /**
+     * Emits what leaving the try blocks after the first "keep" ones needs, innermost first.
+     * Their finally blocks are compiled as if these were already left.
+     */
+    fn exit_tries(&mut self, keep: usize) -> Result<(), GiffiError> {
+        let tries = std::mem::take(&mut self.state.tries);
+        for idx in (keep..tries.len()).rev() {
+            self.state.tries = tries[..idx].to_vec();
+            self.emit(OpCode::PopHandler);
+            if let Some(finally) = &tries[idx].finally {
+                self.compile_block(finally)?;
+            }
+        }
+        self.state.tries = tries;
+        Ok(())
+    }
+
+    /**
+     * "break" and "continue" leave the try blocks inside of the innermost loop
+     */
+    fn exit_loop_tries(&mut self) -> Result<(), GiffiError> {
+        let loops = self.state.loops.len();
+        let keep = self.state.tries.iter().take_while(|try_block| try_block.loops < loops).count();
+        self.exit_tries(keep)
+    }
+
+    /**
+     * Compiles the function and emits the instruction creating its closure.
+     */
+    fn compile_function(&mut self, fn_name: &String, args: &Vec<String>, body: &Vec<Stmt>) -> Result<(), GiffiError> {
+        let span = self.span;
+        let state = FunctionState::new(fn_name.clone(), args.len() as u32, false);
+        self.enclosing.push(std::mem::replace(&mut self.state, state));
+
+        // Arguments are pushed by the caller, so they're the first locals.
+        for arg in args {
+            self.declare_local(arg)?;
+        }
+        self.compile_body(body)?;
+        self.emit_return_null();
+        let state = std::mem::replace(&mut self.state, self.enclosing.pop().unwrap());
+
+        self.span = span;
+        self.state.function.chunk.functions.push(Rc::new(state.function));
+        let idx = (self.state.function.chunk.functions.len() - 1) as u32;
+        self.emit(OpCode::Closure(idx));
+        Ok(())
+    }
+
+    fn get_variable(&mut self, var_name: &String) {
+        if let Some(slot) = self.state.resolve_local(var_name) {
+            self.emit(OpCode::GetLocal(slot));
+            return;
+        }
+        if let Some(idx) = self.resolve_upvalue(var_name) {
+            self.emit(OpCode::GetUpvalue(idx));
+            return;
+        }
+        let slot = self.global_slot(var_name);
+        self.emit(OpCode::GetGlobal(slot));
+    }
+
+    fn set_variable(&mut self, var_name: &String) {
+        if let Some(slot) = self.state.resolve_local(var_name) {
+            self.emit(OpCode::SetLocal(slot));
+            return;
+        }
+        if let Some(idx) = self.resolve_upvalue(var_name) {
+            self.emit(OpCode::SetUpvalue(idx));
+            return;
+        }
+        let slot = self.global_slot(var_name);
+        self.emit(OpCode::SetGlobal(slot));
+    }
+
+    /**
+     * Looks for the variable in the enclosing functions, every function in between captures it as well.
+     */
+    fn resolve_upvalue(&mut self, var_name: &String) -> Option<u32> {
+        if self.enclosing.len() == 0 {
+            return None;
+        }
+        let level = self.enclosing.len() - 1;
+        let upvalue = Compiler::resolve_enclosing(&mut self.enclosing, level, var_name)?;
+        return Some(self.state.add_upvalue(upvalue));
+    }
+
+    fn resolve_enclosing(states: &mut Vec<FunctionState>, level: usize, var_name: &String) -> Option<UpvalueRef> {
+        if let Some(slot) = states[level].resolve_local(var_name) {
+            states[level].locals[slot as usize].captured = true;
+            return Some(UpvalueRef { is_local: true, index: slot });
+        }
+        if level == 0 {
+            return None;
+        }
+        let upvalue = Compiler::resolve_enclosing(states, level - 1, var_name)?;
+        let idx = states[level].add_upvalue(upvalue);
+        return Some(UpvalueRef { is_local: false, index: idx });
+    }
+
+    fn global_slot(&mut self, var_name: &String) -> u32 {
+        let next_slot = self.global_names.len();
+        *self.global_names.entry(var_name.clone()).or_insert(next_slot) as u32
+    }
+
+    /**
+     * The value of the local is expected to be on the top of the stack.
+     */
+    fn declare_local(&mut self, var_name: &String) -> Result<(), GiffiError> {
+        for local in self.state.locals.iter().rev() {
+            if local.depth < self.state.scope_depth {
+                break;
+            }
+            if &local.name == var_name {
+                return Err(self.error(format!("A variable called '{}' already exists!", var_name)));
+            }
+        }
+        self.state.locals.push(Local { name: var_name.clone(), depth: self.state.scope_depth, captured: false });
+        Ok(())
+    }
+
+    fn begin_loop(&mut self, break_depth: u32, continue_depth: u32) {
+        self.state.loops.push(Loop { break_depth: break_depth, continue_depth: continue_depth, breaks: vec![], continues: vec![] });
+    }
+
+    fn begin_scope(&mut self) {
+        self.state.scope_depth += 1;
+    }
+
+    fn end_scope(&mut self) {
+        self.pop_locals_deeper_than(self.state.scope_depth - 1);
+        self.forget_scope();
+    }
+
+    /**
+     * Forgets the locals of the scope without popping them, for scopes whose end is never reached.
+     */
+    fn forget_scope(&mut self) {
+        self.state.scope_depth -= 1;
+        while self.state.locals.len() > 0 && self.state.locals.last().unwrap().depth > self.state.scope_depth {
+            self.state.locals.pop();
+        }
+    }
+
+    /**
+     * Emits the pops, but doesn't forget the locals. Used when jumping out of scopes.
+     */
+    fn pop_locals_deeper_than(&mut self, depth: u32) {
+        for idx in (0..self.state.locals.len()).rev() {
+            if self.state.locals[idx].depth <= depth {
+                break;
+            }
+            if self.state.locals[idx].captured {
+                self.emit(OpCode::CloseUpvalue);
+            }
+            else {
+                self.emit(OpCode::Pop);
+            }
+        }
+    }
+
+    fn emit(&mut self, op: OpCode) -> usize {
+        self.state.function.chunk.emit(op, self.span)
+    }
+
+    fn emit_constant(&mut self, value: Value) {
+        let idx = self.state.function.chunk.add_constant(value);
+        self.emit(OpCode::Constant(idx));
+    }
+
+    fn emit_return_null(&mut self) {
+        self.emit_constant(Value::Null);
+        self.emit(OpCode::Return);
+    }
+
+    /**
+     * Points the jump at "idx" to the next instruction.
+     */
+    fn patch_jump(&mut self, idx: usize) {
+        let target = self.state.function.chunk.code.len() as u32;
+        match &mut self.state.function.chunk.code[idx] {
+            OpCode::Jump(to) | OpCode::JumpIfFalse(to) |
+            OpCode::JumpIfFalseOrPop(to) | OpCode::JumpIfTrueOrPop(to) | OpCode::IterNext(to, _) |
+            OpCode::PushHandler(to) => { *to = target; }
+            op => { panic!("{:?} is not a jump", op); }
+        }
+    }
+
+    fn patch_jumps(&mut self, jumps: Vec<usize>) {
+        for jump in jumps {
+            self.patch_jump(jump);
+        }
+    }
+
+    fn error(&self, msg: String) -> GiffiError {
+        GiffiError::new(ErrorKind::Compile, msg, self.span)
+    }
+}
+
+impl FunctionState {
+    fn new(name: String, arity: u32, is_script: bool) -> FunctionState {
+        FunctionState {
+            function: FunctionProto {
+                name: name,
+                arity: arity,
+                chunk: Chunk::default(),
+                upvalues: vec![],
+            },
+            locals: vec![],
+            loops: vec![],
+            tries: vec![],
+            scope_depth: 0,
+            is_script: is_script,
+        }
+    }
+
+    fn resolve_local(&self, var_name: &String) -> Option<u32> {
+        for (slot, local) in self.locals.iter().enumerate().rev() {
+            if &local.name == var_name {
+                return Some(slot as u32);
+            }
+        }
+        None
+    }
+
+    /**
+     * Returns the index of the upvalue, capturing the same variable twice reuses the upvalue.
+     */
+    fn add_upvalue(&mut self, upvalue: UpvalueRef) -> u32 {
+        if let Some(idx) = self.function.upvalues.iter().position(|u| *u == upvalue) {
+            return idx as u32;
+        }
+        self.function.upvalues.push(upvalue);
+        (self.function.upvalues.len() - 1) as u32
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use crate::bytecode::{OpCode, UpvalueRef};
+    use crate::lexer::{Lexer};
+    use crate::parser::{Parser};
+    use super::Compiler;
+
+    #[test]
+    fn test_variable_resolution() {
+        let statements = Parser::parse(Lexer::lex(String::from("
+        let g = 1;
+        fn foo(a) {
+            let b = a + g;
+            return b;
+        }
+        ")).unwrap()).unwrap();
+        let mut globals = HashMap::new();
+        let script = Compiler::compile(&statements, &mut globals).unwrap();
+        assert_eq!(globals.get("g"), Some(&0));
+
+        // Arguments are the first locals, globals are looked up by slot
+        let foo = &script.chunk.functions[0];
+        assert_eq!(foo.arity, 1);
+        assert_eq!(foo.chunk.code[0], OpCode::GetLocal(0));
+        assert_eq!(foo.chunk.code[1], OpCode::GetGlobal(0));
+        assert_eq!(foo.chunk.code[2], OpCode::Add);
+        assert_eq!(foo.chunk.code[3], OpCode::GetLocal(1));
+        assert_eq!(foo.chunk.code[4], OpCode::Return);
+    }
+
+    #[test]
+    fn test_block_locals_are_popped() {
+        let statements = Parser::parse(Lexer::lex(String::from("
+        while true {
+            let x = 1;
+            if x { let y = 2; break; }
+        }
+        ")).unwrap()).unwrap();
+        let mut globals = HashMap::new();
+        let script = Compiler::compile(&statements, &mut globals).unwrap();
+
+        // "break" has to pop both "y" and "x" before jumping out of the loop
+        let code = &script.chunk.code;
+        let brk = code.iter().position(|op| matches!(op, OpCode::Jump(_))).unwrap();
+        assert_eq!(code[brk - 1], OpCode::Pop);
+        assert_eq!(code[brk - 2], OpCode::Pop);
+    }
+
+    #[test]
+    fn test_upvalues() {
+        let statements = Parser::parse(Lexer::lex(String::from("
+        fn outer(a) {
+            let b = 2;
+            return fn() {
+                return fn() { return a + b; };
+            };
+        }
+        ")).unwrap()).unwrap();
+        let mut globals = HashMap::new();
+        let script = Compiler::compile(&statements, &mut globals).unwrap();
+
+        // The middle function captures the locals so the innermost one can capture them from it
+        let outer = &script.chunk.functions[0];
+        let middle = &outer.chunk.functions[0];
+        let inner = &middle.chunk.functions[0];
+        assert_eq!(middle.upvalues, vec![UpvalueRef { is_local: true, index: 0 }, UpvalueRef { is_local: true, index: 1 }]);
+        assert_eq!(inner.upvalues, vec![UpvalueRef { is_local: false, index: 0 }, UpvalueRef { is_local: false, index: 1 }]);
+        assert_eq!(inner.chunk.code[0], OpCode::GetUpvalue(0));
+        assert_eq!(inner.chunk.code[1], OpCode::GetUpvalue(1));
+    }
+}
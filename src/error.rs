@@ -0,0 +1,98 @@
+use std::fmt;
+
+use crate::ast::Span;
+use crate::value::Value;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorKind {
+    Lex,
+    Parse,
+    Compile,
+    Runtime,
+    // More specific runtime errors, so scripts can tell them apart in a catch
+    DivisionByZero,
+    Overflow,
+    IndexOutOfBounds,
+    KeyNotFound, // Missing map key or struct field
+    Type,        // Operation on the wrong type of value
+    Destructure, // Value doesn't have the shape of the pattern it's destructured with
+    Import,
+    Io,
+    Thrown, // "throw" of a value which isn't an error
+}
+
+impl ErrorKind {
+    /**
+     * Inverse of the Display implementation, used when an error value is thrown again
+     */
+    pub fn from_name(name: &str) -> Option<ErrorKind> {
+        match name {
+            "Lexer" => Some(ErrorKind::Lex),
+            "Parser" => Some(ErrorKind::Parse),
+            "Compiler" => Some(ErrorKind::Compile),
+            "Runtime" => Some(ErrorKind::Runtime),
+            "DivisionByZero" => Some(ErrorKind::DivisionByZero),
+            "Overflow" => Some(ErrorKind::Overflow),
+            "IndexOutOfBounds" => Some(ErrorKind::IndexOutOfBounds),
+            "KeyNotFound" => Some(ErrorKind::KeyNotFound),
+            "Type" => Some(ErrorKind::Type),
+            "Destructure" => Some(ErrorKind::Destructure),
+            "Import" => Some(ErrorKind::Import),
+            "Io" => Some(ErrorKind::Io),
+            "Thrown" => Some(ErrorKind::Thrown),
+            _ => None,
+        }
+    }
+}
+
+/**
+ * Returned by every stage, nothing exits the process so the REPL and embedders can
+ * report the error and keep going.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct GiffiError {
+    pub kind: ErrorKind,
+    pub message: String,
+    pub span: Span,
+    pub value: Option<Value>, // What a script caught or threw, the catch creates one from the error otherwise
+}
+
+impl GiffiError {
+    pub fn new(kind: ErrorKind, message: String, span: Span) -> GiffiError {
+        GiffiError {
+            kind: kind,
+            message: message,
+            span: span,
+            value: None
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ErrorKind::Lex => "Lexer",
+            ErrorKind::Parse => "Parser",
+            ErrorKind::Compile => "Compiler",
+            ErrorKind::Runtime => "Runtime",
+            ErrorKind::DivisionByZero => "DivisionByZero",
+            ErrorKind::Overflow => "Overflow",
+            ErrorKind::IndexOutOfBounds => "IndexOutOfBounds",
+            ErrorKind::KeyNotFound => "KeyNotFound",
+            ErrorKind::Type => "Type",
+            ErrorKind::Destructure => "Destructure",
+            ErrorKind::Import => "Import",
+            ErrorKind::Io => "Io",
+            ErrorKind::Thrown => "Thrown",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl fmt::Display for GiffiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} error at {}:{} '{}'", self.kind, self.span.line, self.span.column, self.message)
+    }
+}
+
+impl std::error::Error for GiffiError {}
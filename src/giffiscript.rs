@@ -1,6 +1,7 @@
 use crate::lexer::{Lexer};
 use crate::parser::{Parser};
 use crate::interpreter::{Interpreter};
+use crate::error::{GiffiError};
 
 pub struct GiffiScript {
     interpreter: Interpreter
@@ -13,13 +14,13 @@ impl GiffiScript {
         }
     }
 
-    pub fn execute(&mut self, code: String) {
+    pub fn execute(&mut self, code: String) -> Result<(), GiffiError> {
         use colored::Colorize;
         use std::time::Instant;
 
         println!("{}", format!("<---Lexing Started!--->").green().bold());
             let now = Instant::now();
-            let ltokens = Lexer::lex(code);
+            let ltokens = Lexer::lex(code)?;
             let end = Instant::now();
             let lexer_time = end - now;
             println!("Lexer Result: {:#?}", ltokens);
@@ -27,22 +28,21 @@ impl GiffiScript {
 
         println!("{}", format!("<---Parsing Started!--->").cyan().bold());
             let now = Instant::now();
-            let ptokens = Parser::parse(ltokens, false);
+            let statements = Parser::parse(ltokens)?;
             let end = Instant::now();
             let parser_time = end - now;
-        println!("Parser Result: {:#?}", ptokens);
+        println!("Parser Result: {:#?}", statements);
         println!("{}", format!(">---Parsing Ended!---<").cyan().bold());
         
         let now = Instant::now();
-        self.interpreter.execute_tokens(&ptokens);
+        let result = self.interpreter.execute(&statements);
         let end = Instant::now();
         let interpreting_time = end - now;
 
         println!("Lexing Time: {:?}", lexer_time);
         println!("Parsing Time: {:?}", parser_time);
         println!("Interpriting Time: {:?}", interpreting_time);
-
-
+        result
     }
 }
 
@@ -57,8 +57,8 @@ mod test {
      */
     fn test_code(code: String, expected: Value) {
         let mut m = GiffiScript::new();
-        m.execute(code);
-        assert_eq!(m.interpreter.get_variable_value(&"r".to_string()), expected);
+        m.execute(code).expect("script failed");
+        assert_eq!(m.interpreter.get_variable_value(&"r".to_string()).unwrap(), expected);
     }
 
     #[test]
@@ -249,7 +249,7 @@ mod test {
         let code = String::from("
         let r = [1,2,3,true,5,6,7];
         ");
-        test_code(code, Value::Array(
+        test_code(code, Value::array(
         vec![
             Value::Int(1),
             Value::Int(2),
@@ -267,7 +267,7 @@ mod test {
         let arr = [50,25];
         let r = [arr[0]+arr[1],\"Hello\", 10*2, false];
         ");
-        test_code(code, Value::Array(
+        test_code(code, Value::array(
         vec![
             Value::Int(75),
             Value::Literal("Hello".to_string()),
@@ -284,7 +284,7 @@ mod test {
         }
         let r = returns_an_array();
         ");
-        test_code(code, Value::Array(
+        test_code(code, Value::array(
         vec![
             Value::Int(75),
             Value::Literal("Hello".to_string()),
@@ -292,4 +292,1307 @@ mod test {
             Value::Boolean(false)
         ]));
     }
+
+    #[test]
+    fn test_while_loop_locals() {
+        // Locals declared in the loop body are fresh every iteration
+        let code = String::from("
+        let r = 0;
+        let idx = 0;
+        while idx < 10 {
+            let doubled = idx * 2;
+            if doubled > 5 {
+                let tmp = doubled;
+                r = r + tmp;
+            }
+            idx = idx + 1;
+        }
+        ");
+        test_code(code, Value::Int(84));
+    }
+
+    #[test]
+    fn test_array_element_assignment_in_loop() {
+        let code = String::from("
+        fn fill(size) {
+            let arr = [0, 0, 0, 0];
+            let i = 0;
+            while i < size {
+                arr[i] = i * i;
+                i = i + 1;
+            }
+            return arr;
+        }
+        let r = fill(4);
+        ");
+        test_code(code, Value::array(vec![Value::Int(0), Value::Int(1), Value::Int(4), Value::Int(9)]));
+    }
+
+    #[test]
+    fn test_early_return_from_nested_blocks() {
+        let code = String::from("
+        fn find(arr, size, needle) {
+            let i = 0;
+            while i < size {
+                let item = arr[i];
+                if item == needle {
+                    if i > 0 {
+                        return i;
+                    }
+                    return -1;
+                }
+                i = i + 1;
+            }
+            return null;
+        }
+        let r = [find([4, 5, 6], 3, 6), find([4, 5, 6], 3, 4), find([4, 5, 6], 3, 7)];
+        ");
+        test_code(code, Value::array(vec![Value::Int(2), Value::Int(-1), Value::Null]));
+    }
+
+    #[test]
+    fn test_break_and_continue() {
+        let code = String::from("
+        let r = 0;
+        let i = 0;
+        while true {
+            let next = i + 1;
+            i = next;
+            if i > 10 {
+                break;
+            }
+            if i % 2 == 0 {
+                let skipped = i;
+                continue;
+            }
+            r = r + i;
+        }
+        ");
+        test_code(code, Value::Int(25));
+    }
+
+    #[test]
+    fn test_nested_loops() {
+        // break and continue only leave the innermost loop
+        let code = String::from("
+        fn count() {
+            let total = 0;
+            let outer = 0;
+            while outer < 5 {
+                let inner = 0;
+                while true {
+                    inner = inner + 1;
+                    if inner == 2 {
+                        continue;
+                    }
+                    if inner > outer {
+                        break;
+                    }
+                    total = total + 1;
+                }
+                outer = outer + 1;
+            }
+            return total;
+        }
+        let r = count();
+        ");
+        test_code(code, Value::Int(7));
+    }
+
+    #[test]
+    fn test_recursive_calls() {
+        let code = String::from("
+        fn fib(n) {
+            if n < 2 {
+                return n;
+            }
+            return fib(n - 1) + fib(n - 2);
+        }
+        let r = fib(15);
+        ");
+        test_code(code, Value::Int(610));
+    }
+
+    #[test]
+    fn test_functions_are_values() {
+        let code = String::from("
+        fn twice(f, x) {
+            return f(f(x));
+        }
+        fn inc(x) {
+            return x + 1;
+        }
+        let also_inc = inc;
+        let r = [twice(inc, 1), twice(fn(x) { return x * 3; }, 2), also_inc == inc, inc == twice];
+        ");
+        test_code(code, Value::array(vec![Value::Int(3), Value::Int(18), Value::Boolean(true), Value::Boolean(false)]));
+    }
+
+    #[test]
+    fn test_closure_counter() {
+        // Captured variables outlive the call and are shared between closures
+        let code = String::from("
+        fn make_counter() {
+            let count = 0;
+            let inc = fn() {
+                count = count + 1;
+                return count;
+            };
+            let get = fn() { return count; };
+            return [inc, get];
+        }
+        let a = make_counter();
+        let b = make_counter();
+        let inc = a[0];
+        let get = a[1];
+        inc();
+        inc();
+        let inc_b = b[0];
+        inc_b();
+        let r = [get(), inc(), inc_b()];
+        ");
+        test_code(code, Value::array(vec![Value::Int(2), Value::Int(3), Value::Int(2)]));
+    }
+
+    #[test]
+    fn test_nested_functions() {
+        let code = String::from("
+        fn make_adder(a) {
+            fn adder(b) {
+                return fn(c) { return a + b + c; };
+            }
+            return adder;
+        }
+        fn sum_to(n) {
+            fn rec(i) {
+                if i == 0 {
+                    return 0;
+                }
+                return i + rec(i - 1);
+            }
+            return rec(n);
+        }
+        let r = [make_adder(1)(10)(100), sum_to(10)];
+        ");
+        test_code(code, Value::array(vec![Value::Int(111), Value::Int(55)]));
+    }
+
+    #[test]
+    fn test_closures_in_loops() {
+        // Every iteration has its own copy of the loop body's locals
+        let code = String::from("
+        let fns = [0, 0, 0];
+        let i = 0;
+        while i < 3 {
+            let captured = i * 10;
+            fns[i] = fn() { return captured; };
+            i = i + 1;
+        }
+        let first = fns[0];
+        let last = fns[2];
+        let r = [first(), last()];
+        ");
+        test_code(code, Value::array(vec![Value::Int(0), Value::Int(20)]));
+    }
+
+    #[test]
+    fn test_for_loops() {
+        let code = String::from("
+        let sum = 0;
+        for (let i = 0; i < 10; i += 1) {
+            if i % 2 == 0 { continue; }
+            if i > 7 { break; }
+            sum += i;
+        }
+        let fns = [0, 0, 0];
+        for (let i = 0; i < 3; i += 1) {
+            fns[i] = fn() { return i; };
+        }
+        let pairs = 0;
+        for (let a = 0; a < 3; a += 1) {
+            for (let b = a; b < 3; b += 1) {
+                pairs += 1;
+            }
+        }
+        let n = 0;
+        for (;;) {
+            n += 1;
+            if n == 5 { break; }
+        }
+        let f0 = fns[0];
+        let f2 = fns[2];
+        let r = [sum, f0(), f2(), pairs, n];
+        ");
+        test_code(code, Value::array(vec![Value::Int(16), Value::Int(0), Value::Int(2), Value::Int(6), Value::Int(5)]));
+    }
+
+    #[test]
+    fn test_for_in() {
+        let code = String::from("
+        let total = 0;
+        for x in [1, 2, 3, 4] {
+            if x == 3 { continue; }
+            total += x;
+        }
+        let weighted = 0;
+        for i, x in [5, 6, 7] {
+            weighted += i * x;
+        }
+        let scores = { \"a\": 1, \"b\": 2 };
+        let names = \"\";
+        for key in scores {
+            names += key;
+        }
+        let value_sum = 0;
+        for key, value in scores {
+            value_sum += scores[key] * value;
+        }
+        let reversed = \"\";
+        for c in \"abc\" {
+            reversed = c + reversed;
+        }
+        let word = \"aé😀b\";
+        let matching = 0;
+        for i, c in word {
+            if word[i] == c { matching += 1; }
+            reversed = c + reversed;
+        }
+        fn first_over(items, limit) {
+            for item in items {
+                if item > limit { return item; }
+            }
+            return null;
+        }
+        let fns = [0, 0];
+        for i, item in [\"x\", \"y\"] {
+            fns[i] = fn() { return item; };
+        }
+        let f1 = fns[1];
+        let r = [total, weighted, names, value_sum, reversed, matching, first_over([1, 5, 9], 4), f1()];
+        ");
+        test_code(code, Value::array(vec![
+            Value::Int(7),
+            Value::Int(20),
+            Value::Literal("ab".to_string()),
+            Value::Int(5),
+            Value::Literal("b😀éacba".to_string()),
+            Value::Int(4),
+            Value::Int(5),
+            Value::Literal("y".to_string())
+        ]));
+    }
+
+    #[test]
+    fn test_chars_and_bytes() {
+        use crate::error::{ErrorKind};
+
+        let code = String::from("
+        let word = \"héllo\";
+        let upper = \"\";
+        for c in word {
+            if c >= 'a' && c <= 'z' {
+                c = chr(ord(c) - 32);
+            }
+            upper += c;
+        }
+        let kind = \"vowel\";
+        match word[1] {
+            'a' | 'e' | 'i' | 'o' | 'u' => {}
+            _ => { kind = \"other\"; }
+        }
+        let buffer = bytes(4);
+        buffer[0] = 255;
+        buffer[-1] = 0x7F;
+        let view = buffer;
+        let total = 0;
+        for byte in bytes(\"hé\") {
+            total += byte;
+        }
+        let r = [
+            word[0], word[-1], word[1], upper, kind, 'a' < 'b', '\\n', ord('\\u{1F600}'),
+            view, bytes([1, 2, 3])[1..], total, bytes(\"ab\") == bytes([97, 98]), copy(buffer) == buffer
+        ];
+        ");
+        test_code(code, Value::array(vec![
+            Value::Char('h'), Value::Char('o'), Value::Char('é'),
+            Value::Literal("HéLLO".to_string()),
+            Value::Literal("other".to_string()),
+            Value::Boolean(true), Value::Char('\n'), Value::Int(0x1F600),
+            Value::bytes(vec![255, 0, 0, 127]),
+            Value::bytes(vec![2, 3]),
+            Value::Int(104 + 0xC3 + 0xA9),
+            Value::Boolean(true), Value::Boolean(true),
+        ]));
+
+        let mut m = GiffiScript::new();
+        for (code, kind) in [
+            ("let b = bytes(2); b[0] = 256;", ErrorKind::Runtime),
+            ("let c = chr(0xD800);", ErrorKind::Runtime),
+            ("let c = ord(\"a\");", ErrorKind::Type),
+            ("let c = \"ab\"[2];", ErrorKind::IndexOutOfBounds),
+            ("let b = bytes([1, -1]);", ErrorKind::Runtime),
+            ("let b = bytes(9223372036854775807);", ErrorKind::Runtime),
+        ] {
+            let err = m.execute(String::from(code)).unwrap_err();
+            assert_eq!(err.kind, kind, "{}", code);
+        }
+    }
+
+    #[test]
+    fn test_file_bytes() {
+        let path = std::env::temp_dir().join(format!("giffi_bytes_{}.bin", std::process::id()));
+        let code = format!("
+        import \"io\";
+        let data = bytes(\"hi\");
+        data[0] = 0xFF;
+        write_bytes(r\"{}\", data);
+        let r = read_bytes(r\"{}\");
+        ", path.display(), path.display());
+        let mut m = GiffiScript::new();
+        m.execute(code).unwrap();
+        assert_eq!(m.interpreter.get_variable_value(&"r".to_string()).unwrap(), Value::bytes(vec![0xFF, b'i']));
+        assert_eq!(std::fs::read(&path).unwrap(), vec![0xFF, b'i']);
+        std::fs::remove_file(&path).unwrap();
+
+        let err = m.execute(format!("let r = read_bytes(r\"{}\");", path.display())).unwrap_err();
+        assert!(err.message.starts_with("Cannot read"));
+        let err = m.execute(format!("write_bytes(r\"{}\", \"text\");", path.display())).unwrap_err();
+        assert!(err.message.starts_with("Expected Bytes"));
+    }
+
+    #[test]
+    fn test_native_arity() {
+        let mut m = GiffiScript::new();
+        for (code, message) in [
+            ("let x = copy();", "Function 'copy' expects 1 arguments, got 0!"),
+            ("let x = ord();", "Function 'ord' expects 1 arguments, got 0!"),
+            ("let x = ord('a', 'b');", "Function 'ord' expects 1 arguments, got 2!"),
+            ("let x = keys({}, 1);", "Function 'keys' expects 1 arguments, got 2!"),
+            ("import \"math\"; let x = max(1);", "Function 'max' expects 2 arguments, got 1!"),
+        ] {
+            let err = m.execute(String::from(code)).unwrap_err();
+            assert_eq!(err.kind, crate::error::ErrorKind::Runtime, "{}", code);
+            assert_eq!(err.message, message);
+        }
+        // The stack is still intact
+        m.execute(String::from("let x = ord('a') + max(1, 2);")).unwrap();
+        assert_eq!(m.interpreter.get_variable_value(&"x".to_string()).unwrap(), Value::Int(99));
+    }
+
+    #[test]
+    fn test_numeric_literals() {
+        let code = String::from("
+        let mask = 0xFF00FF & 0b1111_0000;
+        let r = [mask, 0o755, -0x10, 1_000_000 / 1_000, 2.5e2, 1e-1 * 10, 3f / 2];
+        ");
+        test_code(code, Value::array(vec![
+            Value::Int(0xF0), Value::Int(493), Value::Int(-16), Value::Int(1000),
+            Value::Float(250.0), Value::Float(1.0), Value::Float(1.5),
+        ]));
+    }
+
+    #[test]
+    fn test_string_interpolation() {
+        let code = String::from("
+        struct Point { x, y }
+        let score = 21;
+        let max = 50;
+        let name = \"giffi\";
+        let p = Point { x: 1, y: 2 };
+        let r = [
+            \"Score: ${score * 2} of ${max}\",
+            \"${name}${\"!\"}\",
+            \"${[1, 2.5, null, true]} ${p} ${{\"a\": 1}[\"a\"]}\",
+            \"${if score > 20 { \"high\" } else { \"low\" }} \\${score} $score\",
+            r\"${score}\",
+            \"a ${\"b ${name} c\"} d\"
+        ];
+        ");
+        test_code(code, Value::array(vec![
+            Value::Literal("Score: 42 of 50".to_string()),
+            Value::Literal("giffi!".to_string()),
+            Value::Literal("[1,2.5,null,true] Point{x:1,y:2} 1".to_string()),
+            Value::Literal("high ${score} $score".to_string()),
+            Value::Literal("${score}".to_string()),
+            Value::Literal("a b giffi c d".to_string()),
+        ]));
+
+        let mut m = GiffiScript::new();
+        let err = m.execute(String::from("let s = \"${}\";")).unwrap_err();
+        assert_eq!(err.kind, crate::error::ErrorKind::Parse);
+        let err = m.execute(String::from("let s = \"${1 2}\";")).unwrap_err();
+        assert_eq!(err.kind, crate::error::ErrorKind::Parse);
+    }
+
+    #[test]
+    fn test_ranges() {
+        let code = String::from("
+        let arr = [10, 20, 30, 40, 50];
+        let text = \"giffiscript\";
+        let sum = 0;
+        for i in 1..=4 {
+            sum += i;
+        }
+        let squares = [0, 0, 0];
+        for i, n in 2..5 {
+            squares[i] = n * n;
+        }
+        let empty = true;
+        for i in 5..2 {
+            empty = false;
+        }
+        arr[-1] = 55;
+        let r = [
+            arr[1..3], arr[..2], arr[3..], arr[-2..], arr[1..=-2], arr[-1],
+            text[..5], text[5..], text[-6..-3],
+            sum, squares, empty, (1..3) == (1..3), (1..3) == (1..=3)
+        ];
+        ");
+        let ints = |values: Vec<i64>| Value::array(values.into_iter().map(Value::Int).collect());
+        test_code(code, Value::array(vec![
+            ints(vec![20, 30]),
+            ints(vec![10, 20]),
+            ints(vec![40, 55]),
+            ints(vec![40, 55]),
+            ints(vec![20, 30, 40]),
+            Value::Int(55),
+            Value::Literal("giffi".to_string()),
+            Value::Literal("script".to_string()),
+            Value::Literal("scr".to_string()),
+            Value::Int(10),
+            ints(vec![4, 9, 16]),
+            Value::Boolean(true),
+            Value::Boolean(true),
+            Value::Boolean(false)
+        ]));
+
+        let mut m = GiffiScript::new();
+        assert!(m.execute(String::from("let a = [1, 2, 3]; let b = a[2..5];")).is_err());
+        assert!(m.execute(String::from("let c = [1, 2, 3]; let d = c[-4];")).is_err());
+        assert!(m.execute(String::from("for i in ..3 { }")).is_err());
+    }
+
+    #[test]
+    fn test_match() {
+        let code = String::from("
+        fn describe(value) {
+            let result = \"\";
+            match value {
+                0 => result = \"zero\",
+                -1 | -2 => result = \"small negative\",
+                \"a\" | \"b\" => result = \"letter\",
+                [x, 0] => result = \"on the x axis\",
+                [x, y] if x == y => { result = \"diagonal\"; }
+                [_, [inner]] => { return inner; }
+                [x, y] => {
+                    result = \"point\";
+                }
+                n if n > 100 => result = \"big\",
+                _ => result = \"other\"
+            }
+            return result;
+        }
+        let words = [0, 0, 0, 0, 0, 0];
+        for i in 10..16 {
+            match [i % 3, i % 5] {
+                [0, 0] => words[i - 10] = \"FizzBuzz\",
+                [0, _] => words[i - 10] = \"Fizz\",
+                [_, 0] => words[i - 10] = \"Buzz\",
+                _ => words[i - 10] = i
+            }
+        }
+        let r = [
+            describe(0), describe(-2), describe(\"b\"), describe([3, 0]), describe([2, 2]),
+            describe([1, [7]]), describe([1, 2]), describe(101), describe(5), words
+        ];
+        ");
+        let literal = |s: &str| Value::Literal(s.to_string());
+        test_code(code, Value::array(vec![
+            literal("zero"),
+            literal("small negative"),
+            literal("letter"),
+            literal("on the x axis"),
+            literal("diagonal"),
+            Value::Int(7),
+            literal("point"),
+            literal("big"),
+            literal("other"),
+            Value::array(vec![literal("Buzz"), Value::Int(11), literal("Fizz"), Value::Int(13), Value::Int(14), literal("FizzBuzz")])
+        ]));
+
+        let mut m = GiffiScript::new();
+        assert!(m.execute(String::from("match 1 { x | 2 => { } }")).is_err());
+    }
+
+    #[test]
+    fn test_if_expressions() {
+        let code = String::from("
+        struct Point { x, y }
+        fn max(a, b) {
+            return if a > b { a } else { b };
+        }
+        fn sign(n) {
+            return if n < 0 { -1 } else if n == 0 { 0 } else { 1 };
+        }
+        let calls = 0;
+        fn touch(value) {
+            calls += 1;
+            return value;
+        }
+        let picked = if true { touch(\"yes\") } else { touch(\"no\") };
+        let origin = if max(1, 2) == 2 { Point { x: 0, y: 0 } } else { null };
+        let r = [max(3, 7), max(9, 2), sign(-5), sign(0), sign(5), picked, calls, origin.x, 1 + if false { 1 } else { 2 } * 3];
+        ");
+        test_code(code, Value::array(vec![
+            Value::Int(7),
+            Value::Int(9),
+            Value::Int(-1),
+            Value::Int(0),
+            Value::Int(1),
+            Value::Literal("yes".to_string()),
+            Value::Int(1),
+            Value::Int(0),
+            Value::Int(7)
+        ]));
+
+        let mut m = GiffiScript::new();
+        assert!(m.execute(String::from("let a = if true { 1 };")).is_err());
+    }
+
+    #[test]
+    fn test_destructuring() {
+        let code = String::from("
+        struct Point { x, y }
+        fn min_max(items) {
+            let [first, ...rest] = items;
+            let low = first;
+            let high = first;
+            for item in rest {
+                low = if item < low { item } else { low };
+                high = if item > high { item } else { high };
+            }
+            return [low, high];
+        }
+        let [low, high] = min_max([4, 9, 1, 7]);
+        let [head, [inner, _], ...tail] = [1, [2, 3], 4, 5];
+        let [only, ...none] = [6];
+        let {x, y} = Point { x: 3, y: 4 };
+        let a = 1;
+        let b = 2;
+        [a, b] = [b, a];
+        let arr = [0, 0];
+        let p = Point { x: 0, y: 0 };
+        [arr[1], p.x, [a, b]] = [\"set\", 10, [a + b, 0]];
+        fn first_two(items) {
+            match items {
+                [x, y, ...rest] => { return [x + y, rest]; }
+                {x, y} => { return [x + y, null]; }
+                _ => { return null; }
+            }
+        }
+        let r = [low, high, head, inner, tail, only, none, x, y, a, b, arr, p.x, first_two([1, 2, 3]), first_two(Point { x: 5, y: 6 }), first_two([1])];
+        ");
+        let ints = |values: Vec<i64>| Value::array(values.into_iter().map(Value::Int).collect());
+        test_code(code, Value::array(vec![
+            Value::Int(1),
+            Value::Int(9),
+            Value::Int(1),
+            Value::Int(2),
+            ints(vec![4, 5]),
+            Value::Int(6),
+            ints(vec![]),
+            Value::Int(3),
+            Value::Int(4),
+            Value::Int(3),
+            Value::Int(0),
+            Value::array(vec![Value::Int(0), Value::Literal("set".to_string())]),
+            Value::Int(10),
+            Value::array(vec![Value::Int(3), ints(vec![3])]),
+            Value::array(vec![Value::Int(11), Value::Null]),
+            Value::Null
+        ]));
+
+        use crate::error::{ErrorKind};
+        let mut m = GiffiScript::new();
+        assert!(m.execute(String::from("let [c, d] = [1];")).is_err());
+        assert!(m.execute(String::from("let [e, 1] = [1, 1];")).is_err());
+        assert!(m.execute(String::from("let [f, ...g, h] = [1, 2, 3];")).is_err());
+        assert!(m.execute(String::from("let i = 1; let j = 2; [i, j] += [1, 1];")).is_err());
+
+        for code in [
+            "let [k, l] = [1, 2, 3];",
+            "let [k, l, n] = [1, 2];",
+            "let [k, [l, n]] = [1, [2, 3, 4]];",
+            "let [k, l, ...n] = [1];",
+            "let [k] = 1;",
+            "fn f() { let [k, l] = [1, 2, 3]; } f();",
+            "fn g() { let [k, l, n] = [1, 2]; } g();",
+            "let p = 0; let q = 0; [p, q] = [1, 2, 3];",
+        ] {
+            let error = m.execute(String::from(code)).unwrap_err();
+            assert_eq!(error.kind, ErrorKind::Destructure, "{}", code);
+        }
+        m.execute(String::from("let [k, ...l] = [1]; let o = [k, l];")).unwrap();
+        assert_eq!(m.interpreter.get_variable_value(&"o".to_string()).unwrap(), Value::array(vec![Value::Int(1), Value::array(vec![])]));
+    }
+
+    #[test]
+    fn test_try_catch() {
+        let code = String::from("
+        let log = \"\";
+        fn divide(a, b) {
+            return a / b;
+        }
+        let caught = null;
+        try {
+            divide(1, 0);
+            log += \"unreachable\";
+        }
+        catch (e) {
+            caught = e;
+        }
+        let index_kind = null;
+        try {
+            let arr = [1, 2];
+            let x = arr[5];
+        }
+        catch (e) {
+            index_kind = e.kind;
+        }
+        let thrown = null;
+        try {
+            throw [\"custom\", 42];
+        }
+        catch (e) {
+            thrown = e;
+        }
+        fn with_finally() {
+            try {
+                return \"returned\";
+            }
+            finally {
+                log += \"f\";
+            }
+        }
+        let returned = with_finally();
+        for i in 0..5 {
+            try {
+                if i == 1 { continue; }
+                if i == 3 { break; }
+                log += \"01234\"[i..i + 1];
+            }
+            finally {
+                log += \"!\";
+            }
+        }
+        let rethrown = null;
+        try {
+            try {
+                throw \"first\";
+            }
+            catch (e) {
+                throw e + \" then second\";
+            }
+            finally {
+                log += \"inner\";
+            }
+        }
+        catch (e) {
+            rethrown = e;
+        }
+        let import_failed = false;
+        try {
+            import \"does_not_exist.gs\";
+        }
+        catch (e) {
+            import_failed = e.kind;
+        }
+        let type_error = null;
+        try {
+            let x = [1] - \"a\";
+        }
+        catch (e) {
+            type_error = e;
+        }
+        let r = [caught.kind, caught.message, caught.line, index_kind, thrown, returned, log, rethrown, import_failed,
+            type_error.kind, type_error.message];
+        ");
+        test_code(code, Value::array(vec![
+            Value::Literal("DivisionByZero".to_string()),
+            Value::Literal("Division by zero".to_string()),
+            Value::Int(4),
+            Value::Literal("IndexOutOfBounds".to_string()),
+            Value::array(vec![Value::Literal("custom".to_string()), Value::Int(42)]),
+            Value::Literal("returned".to_string()),
+            Value::Literal("f0!!2!!inner".to_string()),
+            Value::Literal("first then second".to_string()),
+            Value::Literal("Import".to_string()),
+            Value::Literal("Type".to_string()),
+            Value::Literal("Cannot apply Sub to Array and String".to_string()),
+        ]));
+    }
+
+    #[test]
+    fn test_stack_overflow() {
+        use crate::error::{ErrorKind};
+        let mut m = GiffiScript::new();
+        let err = m.execute(String::from("fn f(n) { return f(n + 1); } f(0);")).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Runtime);
+        assert!(err.message.contains("Stack overflow"));
+
+        m.execute(String::from("
+        fn depth(n) { if n == 0 { return 0; } return depth(n - 1) + 1; }
+        let deep = depth(5000);
+        let caught = null;
+        try { depth(100000); } catch (e) { caught = e.message; }
+        ")).unwrap();
+        assert_eq!(m.interpreter.get_variable_value(&"deep".to_string()).unwrap(), Value::Int(5000));
+        assert!(m.interpreter.get_variable_value(&"caught".to_string()).unwrap().to_string().contains("Stack overflow"));
+    }
+
+    #[test]
+    fn test_uncaught_errors() {
+        use crate::error::{ErrorKind};
+
+        let mut m = GiffiScript::new();
+        let err = m.execute(String::from("throw \"oops\";")).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Thrown);
+        assert_eq!(err.message, "oops");
+
+        // A finally without a catch throws the same error again
+        let err = m.execute(String::from("let r = 0;\ntry {\n    r = 1 / 0;\n}\nfinally {\n    r = 2;\n}")).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::DivisionByZero);
+        assert_eq!(err.span.line, 3);
+        assert_eq!(m.interpreter.get_variable_value(&"r".to_string()).unwrap(), Value::Int(2));
+
+        // Nothing is left over from the failed script
+        m.execute(String::from("let s = 0; try { s = [][1]; } catch (e) { s = e.message; }")).unwrap();
+        assert!(m.interpreter.get_variable_value(&"s".to_string()).unwrap().to_string().contains("Array too small"));
+    }
+
+    #[test]
+    fn test_map_literal() {
+        let code = String::from("
+        let config = {
+            \"name\": \"giffi\",
+            \"size\": [640, 480],
+            \"nested\": { \"depth\": 2 }
+        };
+        let inner = config[\"nested\"];
+        config[\"size\"] = inner[\"depth\"] * 10;
+        config[\"new\"] = true;
+        let r = [keys(config), config[\"size\"], config[\"new\"]];
+        ");
+        test_code(code, Value::array(vec![
+            Value::array(vec![
+                Value::Literal("name".to_string()),
+                Value::Literal("size".to_string()),
+                Value::Literal("nested".to_string()),
+                Value::Literal("new".to_string())
+            ]),
+            Value::Int(20),
+            Value::Boolean(true)
+        ]));
+    }
+
+    #[test]
+    fn test_map_equality() {
+        let code = String::from("
+        let a = { \"x\": 1, \"y\": [2] };
+        let b = { \"y\": [2], \"x\": 1 };
+        let c = { \"x\": 1 };
+        let r = [a == b, a == c, {} == {}];
+        ");
+        test_code(code, Value::array(vec![Value::Boolean(true), Value::Boolean(false), Value::Boolean(true)]));
+    }
+
+    #[test]
+    fn test_structs() {
+        let code = String::from("
+        struct Point { x, y }
+        impl Point {
+            fn length_squared(self) {
+                return self.x * self.x + self.y * self.y;
+            }
+            fn scaled(self, factor) {
+                return Point { x: self.x * factor, y: self.y * factor };
+            }
+        }
+        let p = Point { y: 4, x: 3 };
+        let q = p.scaled(2);
+        q.y = 0.5;
+        let empty = Point {};
+        let r = [p.length_squared(), q.x, q.y, empty.x, p == Point { x: 3, y: 4 }];
+        ");
+        test_code(code, Value::array(vec![Value::Int(25), Value::Int(6), Value::Float(0.5), Value::Null, Value::Boolean(true)]));
+    }
+
+    #[test]
+    fn test_struct_fields_in_conditions() {
+        // "p.x {" is the start of the body, not a struct literal
+        let code = String::from("
+        struct Button { pressed, on_click }
+        let clicks = 0;
+        let b = Button { pressed: true, on_click: fn(n) { return n + 1; } };
+        while b.pressed {
+            clicks = b.on_click(clicks);
+            if clicks == (Button { pressed: 2 }).pressed {
+                b.pressed = false;
+            }
+        }
+        let r = clicks;
+        ");
+        test_code(code, Value::Int(2));
+    }
+
+    #[test]
+    fn test_struct_errors() {
+        let mut m = GiffiScript::new();
+        m.execute(String::from("struct Point { x, y } let p = Point { x: 1 };")).unwrap();
+        assert!(m.execute(String::from("let a = Point { z: 1 };")).is_err());
+        assert!(m.execute(String::from("let a = p.z;")).is_err());
+        assert!(m.execute(String::from("p.length();")).is_err());
+        assert!(m.execute(String::from("impl Point { let x = 1; }")).is_err());
+        assert!(m.execute(String::from("impl Point { fn a(self) {} fn a(self) {} }")).is_err());
+    }
+
+    #[test]
+    fn test_reference_semantics() {
+        let code = String::from("
+        struct Counter { count }
+        impl Counter {
+            fn bump(self) {
+                self.count = self.count + 1;
+            }
+        }
+        fn push_zero(arr) {
+            arr[0] = 0;
+        }
+        let a = [1, 2];
+        let alias = a;
+        alias[1] = 5;
+        push_zero(a);
+        let m = { \"k\": a };
+        let shared = m[\"k\"];
+        shared[1] = 7;
+        let c = Counter { count: 0 };
+        c.bump();
+        c.bump();
+        let r = [a, c.count];
+        ");
+        test_code(code, Value::array(vec![Value::array(vec![Value::Int(0), Value::Int(7)]), Value::Int(2)]));
+    }
+
+    #[test]
+    fn test_self_containing_values() {
+        let code = String::from("
+        let a = [1];
+        a[0] = a;
+        let b = [1];
+        b[0] = b;
+        let m = { \"x\": 1 };
+        m[\"self\"] = m;
+        let r = [a == b, a == a, a <= b, \"${a}\", \"${m}\", copy(a) == a];
+        ");
+        test_code(code, Value::array(vec![
+            Value::Boolean(true), Value::Boolean(true), Value::Boolean(true),
+            Value::Literal("[[...]]".to_string()),
+            Value::Literal("{x:1,self:[...]}".to_string()),
+            Value::Boolean(true),
+        ]));
+
+        // Error messages print the values with Debug
+        let mut m = GiffiScript::new();
+        let err = m.execute(String::from("let a = [1]; a[0] = a; let c = a.x;")).unwrap_err();
+        assert_eq!(err.kind, crate::error::ErrorKind::Type);
+        assert!(err.message.contains("Array([[...]])"), "{}", err.message);
+        let a = m.interpreter.get_variable_value(&"a".to_string()).unwrap();
+        assert_eq!(format!("{:?}", a), "Array([[...]])");
+    }
+
+    #[test]
+    fn test_copy() {
+        let code = String::from("
+        let grid = [[1, 2], [3, 4]];
+        let cyclic = [0];
+        cyclic[0] = cyclic;
+        let copied = copy(grid);
+        let row = copied[0];
+        row[0] = 9;
+        let cyclic_copy = copy(cyclic);
+        let r = [grid[0], copied[0], cyclic_copy[0] == cyclic_copy];
+        ");
+        test_code(code, Value::array(vec![
+            Value::array(vec![Value::Int(1), Value::Int(2)]),
+            Value::array(vec![Value::Int(9), Value::Int(2)]),
+            Value::Boolean(true)
+        ]));
+    }
+
+    #[test]
+    fn test_nested_indexing() {
+        let code = String::from("
+        fn first() {
+            return [10, 20];
+        }
+        let grid = [[0, 0, 0], [0, 0, 0]];
+        grid[1][2] = 5;
+        grid[0][grid[1][2] - 4] = 1;
+        let tiles = { \"layers\": [{ \"id\": 0 }] };
+        tiles[\"layers\"][0][\"id\"] = 7;
+        let r = [grid, first()[1], [1, 2, 3][1], tiles[\"layers\"][0][\"id\"]];
+        ");
+        test_code(code, Value::array(vec![
+            Value::array(vec![
+                Value::array(vec![Value::Int(0), Value::Int(1), Value::Int(0)]),
+                Value::array(vec![Value::Int(0), Value::Int(0), Value::Int(5)])
+            ]),
+            Value::Int(20),
+            Value::Int(2),
+            Value::Int(7)
+        ]));
+    }
+
+    #[test]
+    fn test_nested_field_assignment() {
+        let code = String::from("
+        struct Tile { kind }
+        struct Map { tiles }
+        let map = Map { tiles: [Tile { kind: 0 }, Tile { kind: 0 }] };
+        map.tiles[1].kind = 3;
+        let r = [map.tiles[0].kind, map.tiles[1].kind];
+        ");
+        test_code(code, Value::array(vec![Value::Int(0), Value::Int(3)]));
+    }
+
+    #[test]
+    fn test_unary_operators() {
+        let code = String::from("
+        let a = 3;
+        let b = 4;
+        let x = 5;
+        let f = 2.5;
+        let r = [-a * b, !(a == b), --x, -f, !0, !\"\", ~a, a -1];
+        ");
+        test_code(code, Value::array(vec![
+            Value::Int(-12),
+            Value::Boolean(true),
+            Value::Int(5),
+            Value::Float(-2.5),
+            Value::Boolean(true),
+            Value::Boolean(true),
+            Value::Int(-4),
+            Value::Int(2)
+        ]));
+
+        let mut m = GiffiScript::new();
+        assert!(m.execute(String::from("let s = -\"text\";")).is_err());
+        assert!(m.execute(String::from("let s = ~1.5;")).is_err());
+    }
+
+    #[test]
+    fn test_comparisons() {
+        let code = String::from("
+        let r = [
+            3 <= 3, 3 >= 4, 2.5 <= 3, 1 >= 0.5,
+            \"apple\" < \"banana\", \"b\" > \"abc\", \"ab\" <= \"ab\",
+            [1, 2] < [1, 3], [1, 2] < [1, 2, 0], [2] >= [1, 9], [] <= []
+        ];
+        ");
+        test_code(code, Value::array(vec![
+            Value::Boolean(true), Value::Boolean(false), Value::Boolean(true), Value::Boolean(true),
+            Value::Boolean(true), Value::Boolean(true), Value::Boolean(true),
+            Value::Boolean(true), Value::Boolean(true), Value::Boolean(true), Value::Boolean(true)
+        ]));
+
+        let mut m = GiffiScript::new();
+        assert!(m.execute(String::from("let x = \"1\" < 2;")).is_err());
+        assert!(m.execute(String::from("let x = [1] < [\"a\"];")).is_err());
+    }
+
+    #[test]
+    fn test_bubble_sort() {
+        let code = String::from("
+        fn sort(arr, size) {
+            let i = 0;
+            while i < size {
+                let j = 0;
+                while j < size - i - 1 {
+                    if arr[j] > arr[j + 1] {
+                        let tmp = arr[j];
+                        arr[j] = arr[j + 1];
+                        arr[j + 1] = tmp;
+                    }
+                    j = j + 1;
+                }
+                i = i + 1;
+            }
+        }
+        let r = [\"pear\", \"apple\", \"fig\"];
+        sort(r, 3);
+        ");
+        test_code(code, Value::array(vec![
+            Value::Literal("apple".to_string()),
+            Value::Literal("fig".to_string()),
+            Value::Literal("pear".to_string())
+        ]));
+    }
+
+    #[test]
+    fn test_bitwise_operators() {
+        let code = String::from("
+        let red = 18;
+        let green = 52;
+        let blue = 86;
+        let color = red << 16 | green << 8 | blue;
+        let flags = 5;
+        let r = [color, (color >> 8) & 255, flags ^ 1, flags & ~1, -16 >> 2, 1 << 3 + 1, 6&3];
+        ");
+        test_code(code, Value::array(vec![
+            Value::Int(0x123456),
+            Value::Int(0x34),
+            Value::Int(4),
+            Value::Int(4),
+            Value::Int(-4),
+            Value::Int(16),
+            Value::Int(2)
+        ]));
+
+        let mut m = GiffiScript::new();
+        assert!(m.execute(String::from("let x = 1 << 64;")).is_err());
+        assert!(m.execute(String::from("let x = 1 >> -1;")).is_err());
+        assert!(m.execute(String::from("let x = 1.5 & 1;")).is_err());
+    }
+
+    #[test]
+    fn test_power_operator() {
+        let code = String::from("
+        let x = 3;
+        let r = [2 ** 3 ** 2, 2 ** 3 ** 2 == 512, -2 ** 2, 2 ** -1, 2.0 ** 0.5 > 1.41, x**2, 2 ** 62];
+        ");
+        test_code(code, Value::array(vec![
+            Value::Int(512),
+            Value::Boolean(true),
+            Value::Int(-4),
+            Value::Float(0.5),
+            Value::Boolean(true),
+            Value::Int(9),
+            Value::Int(1 << 62)
+        ]));
+
+        let mut m = GiffiScript::new();
+        let err = m.execute(String::from("let x = 2 ** 63;")).unwrap_err();
+        assert!(err.message.contains("overflow"));
+        assert!(m.execute(String::from("let x = 10.0 ** 400;")).is_err());
+        assert!(m.execute(String::from("let x = \"a\" ** 2;")).is_err());
+    }
+
+    #[test]
+    fn test_integer_overflow() {
+        use crate::error::{ErrorKind};
+        let code = String::from("
+        let r = [9223372036854775806 + 1, -9223372036854775807 - 1, 3037000499 * 3037000499, -(-9223372036854775807)];
+        ");
+        test_code(code, Value::array(vec![
+            Value::Int(i64::MAX), Value::Int(i64::MIN), Value::Int(3037000499 * 3037000499), Value::Int(i64::MAX)
+        ]));
+
+        let mut m = GiffiScript::new();
+        for code in [
+            "let x = 9223372036854775807 + 1;",
+            "let x = -9223372036854775807 - 2;",
+            "let x = 3000000000 * 4000000000;",
+            "let x = -(-9223372036854775807 - 1);",
+            "let x = 9223372036854775807; x += 1;",
+        ] {
+            let err = m.execute(String::from(code)).unwrap_err();
+            assert_eq!(err.kind, ErrorKind::Overflow, "{}", code);
+        }
+    }
+
+    #[test]
+    fn test_integer_division() {
+        use crate::error::{ErrorKind};
+        let code = String::from("
+        let r = [7 / 2, -7 / 2, 7 % -2, -7 % 2, (-9223372036854775807 - 1) / 1, (-9223372036854775807 - 1) % 2];
+        ");
+        test_code(code, Value::array(vec![
+            Value::Int(3), Value::Int(-3), Value::Int(1), Value::Int(-1), Value::Int(i64::MIN), Value::Int(0)
+        ]));
+
+        let mut m = GiffiScript::new();
+        let err = m.execute(String::from("let x = (-9223372036854775807 - 1) / -1;")).unwrap_err();
+        assert!(err.message.contains("overflow"));
+        let err = m.execute(String::from("let x = (-9223372036854775807 - 1) % -1;")).unwrap_err();
+        assert!(err.message.contains("overflow"));
+        assert!(m.execute(String::from("let x = 1 % 0;")).is_err());
+
+        // Ints divided by Floats go through the same checks
+        let err = m.execute(String::from("let x = (-9223372036854775807 - 1) / -1.0;")).unwrap_err();
+        assert!(err.message.contains("overflow"));
+        let err = m.execute(String::from("let x = (-9223372036854775807 - 1) % -1.5;")).unwrap_err();
+        assert!(err.message.contains("overflow"));
+        assert!(m.execute(String::from("let x = 7 / 0.5;")).is_err());
+
+        let err = m.execute(String::from("import \"math\"; let x = abs(-9223372036854775807 - 1);")).unwrap_err();
+        assert!(err.message.contains("overflow"));
+        m.execute(String::from("let y = abs(-5);")).unwrap();
+        assert_eq!(m.interpreter.get_variable_value(&"y".to_string()).unwrap(), Value::Int(5));
+
+        // Ints mixed with Floats and the math library don't panic on overflow either
+        for code in [
+            "let x = 9223372036854775807 + 1.0;",
+            "let x = (-9223372036854775807 - 1) - 1.0;",
+            "let x = 9223372036854775807 * 2.0;",
+            "let x = sum(9223372036854775807, 1);",
+        ] {
+            let err = m.execute(String::from(code)).unwrap_err();
+            assert_eq!(err.kind, ErrorKind::Overflow, "{}", code);
+        }
+        m.execute(String::from("let caught = 0; try { caught = 9223372036854775807 + 1.0; } catch (e) { caught = e.kind; }")).unwrap();
+        assert_eq!(m.interpreter.get_variable_value(&"caught".to_string()).unwrap(), Value::Literal("Overflow".to_string()));
+    }
+
+    #[test]
+    fn test_short_circuit() {
+        let code = String::from("
+        let calls = 0;
+        fn touch(value) {
+            calls = calls + 1;
+            return value;
+        }
+        let x = null;
+        let input = \"\";
+        let safe = x != null && x[0] == 1;
+        let name = input || \"default\";
+        let first = 0 || null || 7;
+        let last = 1 && \"yes\";
+        let skipped = false && touch(true);
+        let also_skipped = true || touch(false);
+        let r = [safe, name, first, last, skipped, also_skipped, calls, touch(2) && touch(3), calls];
+        ");
+        test_code(code, Value::array(vec![
+            Value::Boolean(false),
+            Value::Literal("default".to_string()),
+            Value::Int(7),
+            Value::Literal("yes".to_string()),
+            Value::Boolean(false),
+            Value::Boolean(true),
+            Value::Int(0),
+            Value::Int(3),
+            Value::Int(2)
+        ]));
+    }
+
+    #[test]
+    fn test_compound_assignment() {
+        let code = String::from("
+        struct Point { x, y }
+        let calls = 0;
+        fn next() {
+            calls += 1;
+            return 1;
+        }
+        let a = 10;
+        a += 5;
+        a -= 3;
+        a *= 2;
+        a /= 4;
+        a %= 4;
+        a **= 3;
+        let bits = 12;
+        bits &= 10;
+        bits |= 1;
+        bits ^= 3;
+        bits <<= 2;
+        bits >>= 1;
+        let arr = [1, 2, 3];
+        arr[next()] += 10;
+        let p = Point { x: 1, y: 2 };
+        p.y *= 21;
+        let s = \"foo\";
+        s += \"bar\";
+        let r = [a, bits, arr, calls, p.y, s];
+        ");
+        test_code(code, Value::array(vec![
+            Value::Int(8),
+            Value::Int(20),
+            Value::array(vec![Value::Int(1), Value::Int(12), Value::Int(3)]),
+            Value::Int(1),
+            Value::Int(42),
+            Value::Literal("foobar".to_string())
+        ]));
+    }
+
+    #[test]
+    fn test_globals_across_executions() {
+        let mut m = GiffiScript::new();
+        m.execute(String::from("let counter = 1; fn bump() { counter = counter + 1; }")).unwrap();
+        m.execute(String::from("bump(); bump();")).unwrap();
+        assert_eq!(m.interpreter.get_variable_value(&"counter".to_string()).unwrap(), Value::Int(3));
+    }
+
+    #[test]
+    fn test_errors_are_recoverable() {
+        use crate::error::{ErrorKind};
+
+        let mut m = GiffiScript::new();
+        m.execute(String::from("let r = 1;")).unwrap();
+
+        let err = m.execute(String::from("let s = \"unterminated;")).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Lex);
+
+        let err = m.execute(String::from("let = 5;")).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Parse);
+
+        let err = m.execute(String::from("break;")).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Compile);
+
+        let err = m.execute(String::from("fn f(a) {\n  return a / 0;\n}\nr = f(1);")).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::DivisionByZero);
+        assert_eq!(err.span.line, 2);
+
+        let err = m.execute(String::from("panic(\"oh no\");")).unwrap_err();
+        assert_eq!(err.message, "oh no");
+
+        // The interpreter still works after the errors
+        m.execute(String::from("r = r + 1;")).unwrap();
+        assert_eq!(m.interpreter.get_variable_value(&"r".to_string()).unwrap(), Value::Int(2));
+    }
+
+    /**
+     * Not run by default, "cargo test --release bench -- --ignored --nocapture" prints how long
+     * the VM takes, so a slower dispatch loop shows up when compared with the previous commit.
+     */
+    #[test]
+    #[ignore]
+    fn bench_fizzbuzz() {
+        use std::time::Instant;
+        let code = String::from("
+        let counts = [0, 0, 0, 0];
+        let idx = 0;
+        while idx < 300000 {
+            idx = idx + 1;
+            if idx % 15 == 0 {
+                counts[0] += 1;
+            }
+            else if idx % 3 == 0 {
+                counts[1] += 1;
+            }
+            else if idx % 5 == 0 {
+                counts[2] += 1;
+            }
+            else {
+                counts[3] += 1;
+            }
+        }
+        let r = counts;
+        ");
+        let mut m = GiffiScript::new();
+        let start = Instant::now();
+        m.execute(code).unwrap();
+        println!("fizzbuzz of 300000 numbers took {:?}", start.elapsed());
+        assert_eq!(m.interpreter.get_variable_value(&"r".to_string()).unwrap(),
+            Value::array(vec![Value::Int(20000), Value::Int(80000), Value::Int(40000), Value::Int(160000)]));
+    }
 }
@@ -1,450 +1,1133 @@
-use std::collections::{HashMap, VecDeque};
-use std::process::exit;
+use std::cell::RefCell;
+use std::collections::{HashMap};
+use std::rc::Rc;
 
+use crate::ast::{Stmt, Span};
+use crate::bytecode::{OpCode, FunctionProto};
+use crate::compiler::{Compiler};
+use crate::error::{GiffiError, ErrorKind};
 use crate::lexer::{Lexer};
-use crate::parser::{Parser, ParserToken};
-use crate::value::{Value, self};
+use crate::parser::{Parser};
+use crate::value::{Value, ValueAdder, ValueE, Function, Closure, Upvalue, Map, Range, StructDef, Object, self};
 
 mod io;
 mod math;
 mod sdl;
 
 /**
- * Used for "return", "break" and "continue" statements 
+ * Added by libraries when imported, arguments and the return value are passed through the stack
  */
-#[derive(Debug, Clone, PartialEq)]
-enum ScopeType {
-    Global,
-    Function,
-    Loop,
-    LoopBody,
-    IfOrElse,
-}
+pub type NativeFunction = fn(*mut Interpreter) -> Result<(), GiffiError>;
+
+const MAX_FRAMES: usize = 10000; // Deeper calls are a stack overflow error instead of running out of memory
 
-#[derive(Debug, Clone, PartialEq)]
-struct Scope {
-    scope_name: String,
-    scope_type: ScopeType,
-    variables: HashMap<String, Value>
+#[derive(Clone)]
+struct CallFrame {
+    closure: Rc<Closure>,
+    ip: usize,
+    base: usize, // Stack index of the first local (the first argument), the function itself is right below
 }
-impl Scope {
-    pub fn new(scope_name: String, scope_type: ScopeType) -> Scope {
-        Scope {
-            scope_name: scope_name,
-            scope_type: scope_type,
-            variables: HashMap::new()
-        }
-    }
+
+/**
+ * Active "try" block, errors jump to its catch
+ */
+#[derive(Clone)]
+struct Handler {
+    frame_depth: usize, // Frame count when the block was entered
+    stack_size: usize,
+    catch_ip: usize,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Clone)]
 pub struct Interpreter {
     imported_files: Vec<String>,
-    funcs: HashMap<String, Vec<ParserToken>>,
-    variables: VecDeque<Scope>,
+    global_names: HashMap<String, usize>,
+    globals: Vec<Option<Value>>, // None when not declared yet
     stack: Vec<Value>,
-    last_op: *const ParserToken
+    frames: Vec<CallFrame>,
+    open_upvalues: Vec<Rc<RefCell<Upvalue>>>, // Upvalues still pointing into the stack
+    handlers: Vec<Handler>,
+    error_struct: Rc<StructDef>, // Type of the caught errors
 }
 
 impl Interpreter {
     pub fn new() -> Interpreter {
-        let mut int = Interpreter { 
+        let mut interpreter = Interpreter {
             imported_files: vec![],
-            funcs: HashMap::new(),
-            variables: VecDeque::new(),
+            global_names: HashMap::new(),
+            globals: vec![],
             stack: vec![],
-            last_op: 0 as *const ParserToken
+            frames: vec![],
+            open_upvalues: vec![],
+            handlers: vec![],
+            error_struct: Rc::new(StructDef {
+                name: "Error".to_string(),
+                fields: vec!["message".to_string(), "kind".to_string(), "line".to_string(), "column".to_string()],
+                methods: RefCell::new(HashMap::new())
+            }),
         };
-        int.start_scope("global".to_string(), ScopeType::Global);
-        return int;
+        // Builtins, which can be used without importing anything.
+        interpreter.declare_native_function(&"panic".to_string(), 1, panic).unwrap();
+        interpreter.declare_native_function(&"keys".to_string(), 1, keys).unwrap();
+        interpreter.declare_native_function(&"copy".to_string(), 1, copy).unwrap();
+        interpreter.declare_native_function(&"ord".to_string(), 1, ord).unwrap();
+        interpreter.declare_native_function(&"chr".to_string(), 1, chr).unwrap();
+        interpreter.declare_native_function(&"bytes".to_string(), 1, bytes).unwrap();
+        interpreter.declare_global(&"Error".to_string(), Value::Struct(interpreter.error_struct.clone())).unwrap();
+        interpreter
     }
 
-    pub fn execute_tokens(&mut self, tokens: &Vec<ParserToken>) {
-        for token in tokens {
-            if let ParserToken::Push(v) = token {
-                self.push(v.clone());
-            }
-            else if let ParserToken::Pop() = token {
-                self.pop();
-            }
-            else if let ParserToken::Call(func_name, arg_tokens) = token {
-                self.call_function(func_name, arg_tokens);
-            }
-            else if let ParserToken::CallNative(native_function) = token {
-                native_function(self);
-            }
-            else if let ParserToken::GetVariable(var_name) = token {
-                self.get_variable(var_name);
-            }
-            else if let ParserToken::DeclareVariable(var_name) = token {
-                self.declare_variable(var_name);
-            }
-            else if let ParserToken::DeclareFunction(fn_name, fn_body) = token {
-                self.declare_function(fn_name, fn_body);
-            }
-            else if let ParserToken::Operation(op) = &token {
-                self.op(op);
-            }
-            else if let ParserToken::Return() = &token {
-                self.return_statement();
-            }
-            else if let ParserToken::MakeArray(arg_count) = &token {
-                self.make_array(*arg_count);
-            }
-            else if let ParserToken::GetArrayElement(expression) = &token {
-                self.get_array_element(expression);
-            }
-            else if let ParserToken::GetVariableArrayElement(variable, expression) = &token {
-                self.get_variable_array_element(variable, expression);
-            }
-            else if let ParserToken::StoreVariable(var_name) = &token {
-                self.store_variable(var_name);
-            }
-            else if let ParserToken::StoreVariableArrayElement(var_name) = &token {
-                self.store_variable_array_element(var_name);
-            }
-            else if let ParserToken::If(true_body, false_body) = &token {
-                self.if_statement(true_body, false_body);
-            }
-            else if let ParserToken::While(check, body) = &token {
-                self.while_loop(check, body);
-            }
-            else if let ParserToken::Break() = &token {
-                self.break_statement();   
-            }
-            else if let ParserToken::Continue() = &token {
-                break;
-            }
-            else if let ParserToken::Import(library) = &token {
-                self.import(library);
+    /**
+     * On error the interpreter is left as it was before the call, except for the globals
+     * and functions declared before the error.
+     */
+    pub fn execute(&mut self, statements: &Vec<Stmt>) -> Result<(), GiffiError> {
+        let script = Compiler::compile(statements, &mut self.global_names)?;
+        self.globals.resize(self.global_names.len(), None);
+
+        let depth = self.frames.len();
+        let stack_size = self.stack.len();
+        let handler_count = self.handlers.len();
+        let closure = Rc::new(Closure { proto: script, upvalues: vec![] });
+        self.push(Value::Function(Function::Script(closure.clone())));
+        self.frames.push(CallFrame {
+            closure: closure,
+            ip: 0,
+            base: stack_size + 1,
+        });
+
+        let result = self.run(depth);
+        if result.is_err() {
+            self.close_upvalues(stack_size);
+            self.frames.truncate(depth);
+            self.stack.truncate(stack_size);
+            self.handlers.truncate(handler_count);
+        }
+        result.map(|_| ())
+    }
+
+    /**
+     * Executes until the frame count drops to "exit_depth", returns the value returned by the last frame.
+     * Errors jump to the innermost "try" entered within these frames, others are returned.
+     */
+    fn run(&mut self, exit_depth: usize) -> Result<Value, GiffiError> {
+        loop {
+            let error = match self.dispatch(exit_depth) {
+                Ok(value) => { return Ok(value); }
+                Err(error) => error,
+            };
+            if self.handlers.last().is_none_or(|handler| handler.frame_depth <= exit_depth) {
+                return Err(error);
             }
-            else {
-                self.error(panic!("Unimplumented operation: {:?}", token));
+
+            let handler = self.handlers.pop().unwrap();
+            let value = self.error_value(error);
+            self.close_upvalues(handler.stack_size);
+            self.frames.truncate(handler.frame_depth);
+            self.stack.truncate(handler.stack_size);
+            self.push(value);
+            self.frames.last_mut().unwrap().ip = handler.catch_ip;
+        }
+    }
+
+    fn dispatch(&mut self, exit_depth: usize) -> Result<Value, GiffiError> {
+        // Cached from the current frame, refreshed whenever the frame changes. The frame's ip is only
+        // written back before instructions which can fail or call, errors and calls read it from there.
+        let mut function = self.frame().closure.clone();
+        let mut base = self.frame().base;
+        let mut ip = self.frame().ip;
+        let mut proto = function.proto.clone();
+        loop {
+            let op = proto.chunk.code[ip];
+            ip += 1;
+
+            // Fast path for the most common instructions, the ones which can fail continue below when they do
+            match op {
+                OpCode::Constant(idx) => {
+                    self.push(proto.chunk.constants[idx as usize].clone());
+                    continue;
+                }
+                OpCode::Pop => {
+                    self.pop_value();
+                    continue;
+                }
+                OpCode::GetLocal(slot) => {
+                    let value = self.stack[base + slot as usize].clone();
+                    self.push(value);
+                    continue;
+                }
+                OpCode::SetLocal(slot) => {
+                    let value = self.pop_value();
+                    self.stack[base + slot as usize] = value;
+                    continue;
+                }
+                OpCode::GetGlobal(slot) => {
+                    if let Some(value) = &self.globals[slot as usize] {
+                        let value = value.clone();
+                        self.push(value);
+                        continue;
+                    }
+                }
+                OpCode::SetGlobal(slot) if self.globals[slot as usize].is_some() => {
+                    let value = self.pop_value();
+                    self.globals[slot as usize] = Some(value);
+                    continue;
+                }
+                OpCode::Jump(to) => {
+                    ip = to as usize;
+                    continue;
+                }
+                OpCode::JumpIfFalse(to) => {
+                    if !self.pop_value().is_true() {
+                        ip = to as usize;
+                    }
+                    continue;
+                }
+                OpCode::Add | OpCode::Sub | OpCode::Mul | OpCode::Div | OpCode::Modulo |
+                OpCode::Less | OpCode::Greater | OpCode::LessEqual | OpCode::GreaterEqual | OpCode::Equal | OpCode::NotEqual |
+                OpCode::BitAnd | OpCode::BitOr | OpCode::BitXor | OpCode::ShiftLeft | OpCode::ShiftRight => {
+                    let top = self.stack.len() - 1;
+                    if let (Value::Int(a), Value::Int(b)) = (&self.stack[top - 1], &self.stack[top]) {
+                        if let Some(r) = Interpreter::int_op(op, *a, *b) {
+                            self.stack.pop();
+                            self.stack[top - 1] = r;
+                            continue;
+                        }
+                    }
+                }
+                _ => {}
             }
+            self.frames.last_mut().unwrap().ip = ip;
 
-            self.last_op = token;
+            match op {
+                OpCode::Constant(_) | OpCode::Pop | OpCode::GetLocal(_) | OpCode::SetLocal(_) | OpCode::Jump(_) | OpCode::JumpIfFalse(_) => {
+                    unreachable!("{:?} is handled by the fast path", op);
+                }
+                OpCode::Dup(n) => {
+                    let start = self.stack.len() - n as usize;
+                    for i in start..self.stack.len() {
+                        let value = self.stack[i].clone();
+                        self.push(value);
+                    }
+                }
+                OpCode::DefineGlobal(slot) => {
+                    let value = self.pop_value();
+                    if self.globals[slot as usize].is_some() {
+                        return Err(self.error(format!("A variable called '{}' already exists!", self.global_name(slot))));
+                    }
+                    self.globals[slot as usize] = Some(value);
+                }
+                OpCode::GetGlobal(slot) => {
+                    if let Some(value) = &self.globals[slot as usize] {
+                        let value = value.clone();
+                        self.push(value);
+                        continue;
+                    }
+                    return Err(self.error(format!("No variable called '{}' exists", self.global_name(slot))));
+                }
+                OpCode::SetGlobal(slot) => {
+                    let value = self.pop_value();
+                    if self.globals[slot as usize].is_none() {
+                        return Err(self.error(format!("No variable called '{}' exists!", self.global_name(slot))));
+                    }
+                    self.globals[slot as usize] = Some(value);
+                }
+                OpCode::GetUpvalue(idx) => {
+                    let value = match &*function.upvalues[idx as usize].borrow() {
+                        Upvalue::Open(slot) => self.stack[*slot].clone(),
+                        Upvalue::Closed(value) => value.clone(),
+                    };
+                    self.push(value);
+                }
+                OpCode::SetUpvalue(idx) => {
+                    let value = self.pop_value();
+                    let mut upvalue = function.upvalues[idx as usize].borrow_mut();
+                    match &mut *upvalue {
+                        Upvalue::Open(slot) => { self.stack[*slot] = value; }
+                        Upvalue::Closed(closed) => { *closed = value; }
+                    }
+                }
+                OpCode::CloseUpvalue => {
+                    self.close_upvalues(self.stack.len() - 1);
+                    self.pop_value();
+                }
+                OpCode::MakeRange(inclusive) => {
+                    let end = self.pop_value();
+                    let start = self.pop_value();
+                    let mut bounds = [None, None];
+                    for (bound, value) in bounds.iter_mut().zip([start, end]) {
+                        match value {
+                            Value::Int(i) => { *bound = Some(i); }
+                            Value::Null => {}
+                            _ => { return Err(self.error(format!("Range bounds must be integers, got {:?}", value))); }
+                        }
+                    }
+                    self.push(Value::Range(Box::new(Range { start: bounds[0], end: bounds[1], inclusive: inclusive })));
+                }
+                OpCode::Interpolate(part_count) => {
+                    let parts = self.stack.split_off(self.stack.len() - part_count as usize);
+                    let string: String = parts.iter().map(|part| part.to_string()).collect();
+                    self.push(Value::Literal(string));
+                }
+                OpCode::MakeArray(arg_count) => {
+                    let array = self.stack.split_off(self.stack.len() - arg_count as usize);
+                    self.push(Value::array(array));
+                }
+                OpCode::MakeMap(entry_count) => {
+                    let entries = self.stack.split_off(self.stack.len() - entry_count as usize * 2);
+                    let mut map = Map::new();
+                    let mut iter = entries.into_iter();
+                    while let (Some(key), Some(value)) = (iter.next(), iter.next()) {
+                        map.insert(key.to_string(), value);
+                    }
+                    self.push(Value::map(map));
+                }
+                OpCode::MakeObject(field_count) => {
+                    let fields = self.stack.split_off(self.stack.len() - field_count as usize * 2);
+                    let def = match self.pop_value() {
+                        Value::Struct(def) => def,
+                        value => { return Err(self.error(format!("{:?} is not a struct", value))); }
+                    };
+                    // Fields which aren't given are null
+                    let mut object = Object { def: def.clone(), fields: vec![Value::Null; def.fields.len()] };
+                    let mut iter = fields.into_iter();
+                    while let (Some(field), Some(value)) = (iter.next(), iter.next()) {
+                        let field = field.to_string();
+                        match def.field_index(&field) {
+                            Some(idx) => { object.fields[idx] = value; }
+                            None => { return Err(self.error_of_kind(ErrorKind::KeyNotFound, format!("Struct '{}' has no field called '{}'", def.name, field))); }
+                        }
+                    }
+                    self.push(Value::object(object));
+                }
+                OpCode::GetField(name) => {
+                    let field = function.proto.chunk.constants[name as usize].to_string();
+                    let object = self.pop_value();
+                    let value = self.get_field(&object, &field)?;
+                    self.push(value);
+                }
+                OpCode::SetField(name) => {
+                    let field = function.proto.chunk.constants[name as usize].to_string();
+                    let assign = self.pop_value();
+                    let object = self.pop_value();
+                    self.set_field(&object, &field, assign)?;
+                }
+                OpCode::GetIndex => {
+                    let index = self.pop_value();
+                    let array = self.pop_value();
+                    let value = self.get_element(&array, &index)?;
+                    self.push(value);
+                }
+                OpCode::SetIndex => {
+                    let assign = self.pop_value();
+                    let index = self.pop_value();
+                    let array = self.pop_value();
+                    self.set_element(&array, index, assign)?;
+                }
+                OpCode::Negate | OpCode::Not | OpCode::BitNot => {
+                    let value = self.pop_value();
+                    let r = self.unary_op(op, value)?;
+                    self.push(r);
+                }
+                OpCode::Add | OpCode::Sub | OpCode::Mul | OpCode::Div | OpCode::Modulo | OpCode::Power |
+                OpCode::Less | OpCode::Greater | OpCode::LessEqual | OpCode::GreaterEqual | OpCode::Equal | OpCode::NotEqual |
+                OpCode::BitAnd | OpCode::BitOr | OpCode::BitXor | OpCode::ShiftLeft | OpCode::ShiftRight => {
+                    let rhs = self.pop_value();
+                    let lhs = self.pop_value();
+                    let r = self.op(op, lhs, rhs)?;
+                    self.push(r);
+                }
+                OpCode::JumpIfFalseOrPop(to) | OpCode::JumpIfTrueOrPop(to) => {
+                    let jump_if = matches!(op, OpCode::JumpIfTrueOrPop(_));
+                    if self.stack.last().is_some_and(|value| value.is_true() == jump_if) {
+                        ip = to as usize;
+                    }
+                    else {
+                        self.pop_value();
+                    }
+                }
+                OpCode::MatchArray(len, has_rest) => {
+                    let value = self.pop_value();
+                    let matches = matches!(&value, Value::Array(array)
+                        if array.borrow().len() == len as usize || (has_rest && array.borrow().len() > len as usize));
+                    self.push(Value::Boolean(matches));
+                }
+                OpCode::CheckArray(len, has_rest) => {
+                    let value = self.pop_value();
+                    let Value::Array(array) = &value else {
+                        return Err(self.error_of_kind(ErrorKind::Destructure, format!("Cannot destructure {} as an array", value.type_name())));
+                    };
+                    let actual = array.borrow().len();
+                    if actual != len as usize && !(has_rest && actual > len as usize) {
+                        let expected = if has_rest { format!("at least {}", len) } else { len.to_string() };
+                        return Err(self.error_of_kind(ErrorKind::Destructure, format!("Cannot destructure an array of {} elements, expected {}", actual, expected)));
+                    }
+                }
+                OpCode::MatchFields(fields) => {
+                    let value = self.pop_value();
+                    let mut matches = false;
+                    if let (Value::Object(object), Value::Array(fields)) = (&value, &function.proto.chunk.constants[fields as usize]) {
+                        let object = object.borrow();
+                        matches = fields.borrow().iter().all(|field| object.get(&field.to_string()).is_some());
+                    }
+                    self.push(Value::Boolean(matches));
+                }
+                OpCode::IterNext(to, var_count) => {
+                    let top = self.stack.len() - 1;
+                    let (Value::Int(position), Value::Int(offset)) = (&self.stack[top - 1], &self.stack[top]) else {
+                        return Err(self.error(format!("Bad iterator position {:?}", &self.stack[top - 1..])));
+                    };
+                    let (position, mut offset) = (*position, *offset as usize);
+                    match self.iter_next(&self.stack[top - 2], position as usize, &mut offset)? {
+                        Some((key, value)) => {
+                            self.stack[top - 1] = Value::Int(position + 1);
+                            self.stack[top] = Value::Int(offset as i64);
+                            if var_count == 2 {
+                                self.push(key);
+                                self.push(value);
+                            }
+                            else if matches!(self.stack[top - 2], Value::Map(_)) {
+                                self.push(key);
+                            }
+                            else {
+                                self.push(value);
+                            }
+                        }
+                        None => {
+                            ip = to as usize;
+                        }
+                    }
+                }
+                OpCode::PushHandler(catch_ip) => {
+                    self.handlers.push(Handler {
+                        frame_depth: self.frames.len(),
+                        stack_size: self.stack.len(),
+                        catch_ip: catch_ip as usize
+                    });
+                }
+                OpCode::PopHandler => {
+                    self.handlers.pop();
+                }
+                OpCode::Throw => {
+                    let value = self.pop_value();
+                    return Err(self.thrown_error(value));
+                }
+                OpCode::Call(arg_count) => {
+                    self.call_value(arg_count as usize)?;
+                    function = self.frame().closure.clone();
+                    base = self.frame().base;
+                    ip = self.frame().ip;
+                    proto = function.proto.clone();
+                }
+                OpCode::Invoke(name, arg_count) => {
+                    let method = function.proto.chunk.constants[name as usize].to_string();
+                    self.invoke(&method, arg_count as usize)?;
+                    function = self.frame().closure.clone();
+                    base = self.frame().base;
+                    ip = self.frame().ip;
+                    proto = function.proto.clone();
+                }
+                OpCode::Return => {
+                    let value = self.pop_value();
+                    let frame = self.frames.pop().unwrap();
+                    self.close_upvalues(frame.base);
+                    // Also removes the called function
+                    self.stack.truncate(frame.base - 1);
+                    if self.frames.len() == exit_depth {
+                        return Ok(value);
+                    }
+                    self.push(value);
+                    function = self.frame().closure.clone();
+                    base = self.frame().base;
+                    ip = self.frame().ip;
+                    proto = function.proto.clone();
+                }
+                OpCode::Closure(idx) => {
+                    let proto = function.proto.chunk.functions[idx as usize].clone();
+                    let mut upvalues = vec![];
+                    for upvalue in &proto.upvalues {
+                        if upvalue.is_local {
+                            upvalues.push(self.capture_upvalue(base + upvalue.index as usize));
+                        }
+                        else {
+                            upvalues.push(function.upvalues[upvalue.index as usize].clone());
+                        }
+                    }
+                    let closure = Rc::new(Closure { proto: proto, upvalues: upvalues });
+                    self.push(Value::Function(Function::Script(closure)));
+                }
+                OpCode::Struct(name, fields) => {
+                    let struct_name = function.proto.chunk.constants[name as usize].to_string();
+                    let mut field_names = vec![];
+                    if let Value::Array(fields) = &function.proto.chunk.constants[fields as usize] {
+                        field_names = fields.borrow().iter().map(|field| field.to_string()).collect();
+                    }
+                    let def = StructDef { name: struct_name, fields: field_names, methods: RefCell::new(HashMap::new()) };
+                    self.push(Value::Struct(Rc::new(def)));
+                }
+                OpCode::Method(name) => {
+                    let method = function.proto.chunk.constants[name as usize].to_string();
+                    let Value::Function(func) = self.pop_value() else {
+                        return Err(self.error(format!("Method '{}' is not a function", method)));
+                    };
+                    let Some(Value::Struct(def)) = self.stack.last() else {
+                        return Err(self.error(format!("Methods can only be added to structs")));
+                    };
+                    if def.methods.borrow().contains_key(&method) {
+                        return Err(self.error(format!("Struct '{}' already has a method called '{}'", def.name, method)));
+                    }
+                    def.methods.borrow_mut().insert(method, func);
+                }
+                OpCode::Import(idx) => {
+                    if let Value::Literal(library) = &function.proto.chunk.constants[idx as usize] {
+                        self.import(library)?;
+                    }
+                }
+            }
         }
     }
 
-    fn import(&mut self, library: &String) {
-        if self.imported_files.contains(&library) {
-            return;
+    fn import(&mut self, library: &String) -> Result<(), GiffiError> {
+        if self.imported_files.contains(library) {
+            return Ok(());
         }
         self.imported_files.push(library.clone());
 
         // std libraries
         if library == "math" {
-            math::import_libs(self);
-            return;
+            return math::import_libs(self);
         }
         if library == "io" {
-            io::import_libs(self);
-            return;
+            return io::import_libs(self);
         }
         if library == "sdl" {
-            sdl::import_libs(self);
-            return;
+            return sdl::import_libs(self);
+        }
+
+        // A failed import can be tried again
+        let result = self.import_file(library);
+        if result.is_err() {
+            self.imported_files.retain(|file| file != library);
         }
+        result
+    }
 
+    fn import_file(&mut self, library: &String) -> Result<(), GiffiError> {
         let code = std::fs::read_to_string(library);
         if code.is_err() {
-            self.error(format!("Could not import file {}!", library));
+            return Err(self.error_of_kind(ErrorKind::Import, format!("Could not import file {}!", library)));
         }
 
         // Literally execute everything that's imported
-        let ltokens = Lexer::lex(code.unwrap());
-        let ptokens = Parser::parse(ltokens, false);
-        self.execute_tokens(&ptokens);
+        let ltokens = Lexer::lex(code.unwrap())?;
+        let statements = Parser::parse(ltokens)?;
+        self.execute(&statements)
     }
 
-    fn index_array(&mut self, array_value: &Value, index: &Value) {
-        if let Value::Int(idx) = index {
-            if let Value::Array(array) = array_value {
-                let i = idx.clone() as usize;
-                if i < array.len() {
-                    self.push(array.get(i).unwrap().clone());
-                    return;
-                }
-                self.error(format!("Array too small ({}) to index at {}", array.len(), i));
-            }
-            self.error(format!("Expecting an array when indexing into it, got {:?} instead!", array_value));
+    /**
+     * What a catch gets, errors which didn't come from "throw" become Error objects.
+     */
+    fn error_value(&self, error: GiffiError) -> Value {
+        if let Some(value) = error.value {
+            return value;
         }
-        self.error(format!("Expecting an INT when indexing into an array, got {:?} instead!", index));
-    }
-
-    fn get_variable_array_element(&mut self, variable: &String, expr: &Vec<ParserToken>) {
-        self.execute_tokens(expr);
-        let idx = self.pop();
-        let array = self.get_variable_value(variable);
-        self.index_array(&array, &idx);
+        Value::object(Object {
+            def: self.error_struct.clone(),
+            fields: vec![
+                Value::Literal(error.message),
+                Value::Literal(error.kind.to_string()),
+                Value::Int(error.span.line as i64),
+                Value::Int(error.span.column as i64)
+            ]
+        })
     }
 
-    fn get_array_element(&mut self, expr: &Vec<ParserToken>) {
-        self.execute_tokens(expr);
-        let idx = self.pop();
-        let array = self.pop();
-        self.index_array(&array, &idx);
+    /**
+     * Error objects keep their kind, message and location when they're thrown again.
+     */
+    fn thrown_error(&self, value: Value) -> GiffiError {
+        let mut error = GiffiError::new(ErrorKind::Thrown, value.to_string(), self.current_span());
+        if let Value::Object(object) = &value {
+            let object = object.borrow();
+            if Rc::ptr_eq(&object.def, &self.error_struct) {
+                let field = |name: &str| object.get(&name.to_string()).cloned().unwrap_or(Value::Null);
+                error.message = field("message").to_string();
+                error.kind = ErrorKind::from_name(&field("kind").to_string()).unwrap_or(ErrorKind::Thrown);
+                if let (Value::Int(line), Value::Int(column)) = (field("line"), field("column")) {
+                    error.span = Span::new(line as u32, column as u32);
+                }
+            }
+        }
+        error.value = Some(value);
+        error
     }
 
-    fn make_array(&mut self, arg_count: u32) {
-        let mut array = vec![];
-        for _ in 0..arg_count {
-            array.push(self.pop());
+    fn get_element(&mut self, value: &Value, index: &Value) -> Result<Value, GiffiError> {
+        if let Value::Map(map) = value {
+            if let Value::Literal(key) = index {
+                if let Some(element) = map.borrow().get(key) {
+                    return Ok(element.clone());
+                }
+                return Err(self.error_of_kind(ErrorKind::KeyNotFound, format!("Map has no key called '{}'", key)));
+            }
+            return Err(self.error_of_kind(ErrorKind::Type, format!("Expecting a String when indexing into a map, got {:?} instead!", index)));
         }
-        array.reverse();
-        self.push(Value::Array(array));
+        if let Value::Range(range) = index {
+            match value {
+                Value::Array(array) => {
+                    let array = array.borrow();
+                    if let Some((start, end)) = range.slice_bounds(array.len()) {
+                        return Ok(Value::array(array[start..end].to_vec()));
+                    }
+                    return Err(self.error_of_kind(ErrorKind::IndexOutOfBounds, format!("Range {} is out of bounds of an array of size {}", index, array.len())));
+                }
+                Value::Literal(string) => {
+                    let chars: Vec<char> = string.chars().collect();
+                    if let Some((start, end)) = range.slice_bounds(chars.len()) {
+                        return Ok(Value::Literal(chars[start..end].iter().collect()));
+                    }
+                    return Err(self.error_of_kind(ErrorKind::IndexOutOfBounds, format!("Range {} is out of bounds of a string of length {}", index, chars.len())));
+                }
+                Value::Bytes(bytes) => {
+                    let bytes = bytes.borrow();
+                    if let Some((start, end)) = range.slice_bounds(bytes.len()) {
+                        return Ok(Value::bytes(bytes[start..end].to_vec()));
+                    }
+                    return Err(self.error_of_kind(ErrorKind::IndexOutOfBounds, format!("Range {} is out of bounds of bytes of size {}", index, bytes.len())));
+                }
+                _ => { return Err(self.error_of_kind(ErrorKind::Type, format!("Expecting an array, a string or bytes when slicing, got {:?} instead!", value))); }
+            }
+        }
+        if let Value::Int(idx) = index {
+            match value {
+                Value::Array(array) => {
+                    let array = array.borrow();
+                    if let Some(i) = Range::resolve(*idx, array.len()).filter(|i| *i < array.len()) {
+                        return Ok(array[i].clone());
+                    }
+                    return Err(self.error_of_kind(ErrorKind::IndexOutOfBounds, format!("Array too small ({}) to index at {}", array.len(), idx)));
+                }
+                Value::Literal(string) => {
+                    let len = string.chars().count();
+                    if let Some(c) = Range::resolve(*idx, len).and_then(|i| string.chars().nth(i)) {
+                        return Ok(Value::Char(c));
+                    }
+                    return Err(self.error_of_kind(ErrorKind::IndexOutOfBounds, format!("String too short ({}) to index at {}", len, idx)));
+                }
+                Value::Bytes(bytes) => {
+                    let bytes = bytes.borrow();
+                    if let Some(i) = Range::resolve(*idx, bytes.len()).filter(|i| *i < bytes.len()) {
+                        return Ok(Value::Int(bytes[i] as i64));
+                    }
+                    return Err(self.error_of_kind(ErrorKind::IndexOutOfBounds, format!("Bytes too small ({}) to index at {}", bytes.len(), idx)));
+                }
+                _ => { return Err(self.error_of_kind(ErrorKind::Type, format!("Expecting an array, a string or bytes when indexing into it, got {:?} instead!", value))); }
+            }
+        }
+        Err(self.error_of_kind(ErrorKind::Type, format!("Expecting an INT when indexing into an array, got {:?} instead!", index)))
     }
 
-    fn while_loop(&mut self, check: &Vec<ParserToken>, body: &Vec<ParserToken>) {
-        self.start_scope("while loop begin".to_string(), ScopeType::Loop);
-        let while_scope_count = self.get_scope_count();
-        'while_loop : loop{
-            // The scope that this loop was in, doesn't exist anymore.
-            if self.get_scope_count() < while_scope_count {
-                break 'while_loop;
+    /**
+     * Item at "position" of a for-in loop as (key, value): (index, element) for arrays,
+     * (index, char) for strings, (index, byte) for bytes, (index, number) for ranges and (key, value) for maps. None when the iterable ran out.
+     * Strings are walked by the byte "offset" of the next char, so each step doesn't have to count the chars before it.
+     */
+    fn iter_next(&self, iterable: &Value, position: usize, offset: &mut usize) -> Result<Option<(Value, Value)>, GiffiError> {
+        match iterable {
+            Value::Array(array) => {
+                return Ok(array.borrow().get(position).map(|element| (Value::Int(position as i64), element.clone())));
             }
-
-            // Evalute
-            self.execute_tokens(check);
-            let continue_looping = self.pop().is_true();
-            if !continue_looping {
-                break 'while_loop;
+            Value::Map(map) => {
+                return Ok(map.borrow().iter().nth(position).map(|(key, value)| (Value::Literal(key.clone()), value.clone())));
+            }
+            Value::Literal(string) => {
+                let next = string.get(*offset..).and_then(|rest| rest.chars().next());
+                if let Some(c) = next {
+                    *offset += c.len_utf8();
+                }
+                return Ok(next.map(|c| (Value::Int(position as i64), Value::Char(c))));
+            }
+            Value::Bytes(bytes) => {
+                return Ok(bytes.borrow().get(position).map(|byte| (Value::Int(position as i64), Value::Int(*byte as i64))));
+            }
+            Value::Range(range) => {
+                if range.start.is_none() {
+                    return Err(self.error(format!("Cannot iterate over {} without a start", iterable)));
+                }
+                return Ok(range.nth(position).map(|i| (Value::Int(position as i64), Value::Int(i))));
+            }
+            _ => {
+                return Err(self.error_of_kind(ErrorKind::Type, format!("Cannot iterate over {:?}", iterable)));
             }
-
-            // Execute the body
-            self.start_scope("While loop".to_string(), ScopeType::LoopBody);
-            self.execute_tokens(body);
-            self.end_scope();
         }
-        self.end_scope();
     }
 
-    fn if_statement(&mut self, true_body: &Vec<ParserToken>, false_body: &Vec<ParserToken>) {
-        let value = self.pop();
-        if value.is_true() {
-            self.start_scope("If block".to_string(), ScopeType::IfOrElse);
-            self.execute_tokens(true_body);
-            self.end_scope();
+    /**
+     * Assigning to a key which isn't in a map yet adds it.
+     */
+    fn set_element(&mut self, value: &Value, index: Value, assign: Value) -> Result<(), GiffiError> {
+        if let Value::Map(map) = value {
+            if let Value::Literal(key) = index {
+                map.borrow_mut().insert(key, assign);
+                return Ok(());
+            }
+            return Err(self.error_of_kind(ErrorKind::Type, format!("Trying to index a map with a value({:?}) which is not a string", index)));
         }
-        else {
-            self.start_scope("Else block".to_string(), ScopeType::IfOrElse);
-            self.execute_tokens(false_body);
-            self.end_scope();
+        if let Value::Array(array) = value {
+            let mut array = array.borrow_mut();
+            if let Value::Int(idx) = &index {
+                if let Some(i) = Range::resolve(*idx, array.len()).filter(|i| *i < array.len()) {
+                    // Assign
+                    array[i] = assign;
+                    return Ok(());
+                }
+                return Err(self.error_of_kind(ErrorKind::IndexOutOfBounds, format!("Trying to index with a value({:?}) which is bigger than size of the array({})", index, array.len())));
+            }
+            return Err(self.error_of_kind(ErrorKind::Type, format!("Trying to index with a value({:?}) which is not an integer", index)));
         }
-    }
-    
-    fn break_statement(&mut self) {
-        while self.get_scope_type() != &ScopeType::Loop {
-            self.end_scope();
+        if let Value::Bytes(bytes) = value {
+            let mut bytes = bytes.borrow_mut();
+            let (Value::Int(idx), Value::Int(byte)) = (&index, &assign) else {
+                return Err(self.error_of_kind(ErrorKind::Type, format!("Bytes are indexed with an integer and hold integers, got {:?} and {:?}", index, assign)));
+            };
+            let Ok(byte) = u8::try_from(*byte) else {
+                return Err(self.error(format!("A byte has to be between 0 and 255, got {}", byte)));
+            };
+            if let Some(i) = Range::resolve(*idx, bytes.len()).filter(|i| *i < bytes.len()) {
+                bytes[i] = byte;
+                return Ok(());
+            }
+            return Err(self.error_of_kind(ErrorKind::IndexOutOfBounds, format!("Trying to index with a value({:?}) which is bigger than size of the bytes({})", index, bytes.len())));
         }
-        self.end_scope();
+        Err(self.error_of_kind(ErrorKind::Type, format!("Trying to index into {:?} which is not an array, a map or bytes!", value)))
     }
 
-    fn return_statement(&mut self) {
-        while self.get_scope_type() != &ScopeType::Function {
-            self.end_scope();
+    fn get_field(&mut self, object: &Value, field: &String) -> Result<Value, GiffiError> {
+        if let Value::Object(object) = object {
+            let object = object.borrow();
+            if let Some(value) = object.get(field) {
+                return Ok(value.clone());
+            }
+            return Err(self.error_of_kind(ErrorKind::KeyNotFound, format!("Struct '{}' has no field called '{}'", object.def.name, field)));
         }
-        self.end_scope();
+        Err(self.error_of_kind(ErrorKind::Type, format!("Cannot read field '{}' of {:?}, it's not a struct", field, object)))
     }
 
-    fn call_function(&mut self, fn_name: &String, arg_tokens: &Vec<ParserToken>) {
-        self.execute_tokens(arg_tokens);
-
-        // global panic, which can be used.
-        if fn_name == "panic" {
-            // There is an argument to be used as a panic message.
-            if self.stack.len() > 0 {
-                let value = self.pop();
-                self.error(value.to_string());
+    fn set_field(&mut self, object: &Value, field: &String, assign: Value) -> Result<(), GiffiError> {
+        if let Value::Object(obj) = object {
+            let mut obj = obj.borrow_mut();
+            if let Some(idx) = obj.def.field_index(field) {
+                obj.fields[idx] = assign;
+                return Ok(());
             }
-            self.error("PANIC".to_string());
+            return Err(self.error_of_kind(ErrorKind::KeyNotFound, format!("Struct '{}' has no field called '{}'", obj.def.name, field)));
         }
+        Err(self.error_of_kind(ErrorKind::Type, format!("Cannot assign field '{}' of {:?}, it's not a struct", field, object)))
+    }
 
-        self.start_scope(fn_name.clone(), ScopeType::Function);
-        let tks = self.funcs.get(fn_name);
-        if tks.is_none() {
-            self.error(format!("No function named '{}' exists!", fn_name));
+    /**
+     * The object and the arguments are already on the stack.
+     * A field holding a function is called as is, methods get the object as their first argument.
+     */
+    fn invoke(&mut self, method: &String, arg_count: usize) -> Result<(), GiffiError> {
+        let receiver = self.stack.len() - arg_count - 1;
+        if let Value::Object(object) = self.stack[receiver].clone() {
+            let field = object.borrow().get(method).cloned();
+            if let Some(value) = field {
+                self.stack[receiver] = value;
+                return self.call_value(arg_count);
+            }
+            let def = object.borrow().def.clone();
+            let func = def.methods.borrow().get(method).cloned();
+            if let Some(func) = func {
+                self.stack.insert(receiver, Value::Function(func));
+                return self.call_value(arg_count + 1);
+            }
+            return Err(self.error(format!("Struct '{}' has no method called '{}'", def.name, method)));
         }
-        self.execute_tokens(&tks.unwrap().clone());
-        self.end_scope();
+        Err(self.error(format!("Cannot call method '{}' of {:?}, it's not a struct", method, self.stack[receiver])))
     }
 
-    fn store_variable_array_element(&mut self, var_name: &String) {
-        let mut value = self.get_variable_value(var_name);
-        let assign = self.pop();
-        let index = self.pop();
+    /**
+     * The function and the arguments are already on the stack.
+     */
+    fn call_value(&mut self, arg_count: usize) -> Result<(), GiffiError> {
+        let base = self.stack.len() - arg_count;
+        match self.stack[base - 1].clone() {
+            Value::Function(Function::Native(fn_name, arity, native_function)) => {
+                // The message of a panic is optional
+                if &*fn_name == "panic" && arg_count == 0 {
+                    return Err(self.error("PANIC".to_string()));
+                }
+                if arity as usize != arg_count {
+                    return Err(self.error(format!("Function '{}' expects {} arguments, got {}!", fn_name, arity, arg_count)));
+                }
+                native_function(self)?;
 
-        
-        if let Value::Array(array) = &mut value {
-            if let Value::Int(idx) = &index {
-                let i = *idx as usize;
-                if i < array.len() {
-                    // Assign
-                    array[i] = assign;
-                    self.push(Value::Array(array.clone()));
-                    self.store_variable(var_name);
-                    return;
+                // Native functions without a return value don't push anything
+                let value = if self.stack.len() > base { self.pop_value() } else { Value::Null };
+                self.stack.truncate(base - 1);
+                self.push(value);
+            }
+            Value::Function(Function::Script(closure)) => {
+                if closure.proto.arity as usize != arg_count {
+                    return Err(self.error(format!("Function '{}' expects {} arguments, got {}!", closure.proto.name, closure.proto.arity, arg_count)));
+                }
+                if self.frames.len() >= MAX_FRAMES {
+                    return Err(self.error(format!("Stack overflow, more than {} nested calls!", MAX_FRAMES)));
                 }
-                self.error(format!("Trying to index with a value({:?}) which is bigger than size of the array({})", index, array.len()));
+                self.frames.push(CallFrame {
+                    closure: closure,
+                    ip: 0,
+                    base: base,
+                });
+            }
+            value => {
+                return Err(self.error_of_kind(ErrorKind::Type, format!("Cannot call {:?}, it's not a function!", value)));
             }
-            self.error(format!("Trying to index with a value({:?}) which is not an integer", index));
         }
-        self.error(format!("Variable \"{}\" is not an array!", var_name));
+        Ok(())
     }
 
-    fn store_variable(&mut self, var_name: &String) {
-        let val = self.pop();
-
-        for idx in 0..self.variables.len() {
-            let scope = self.variables.get_mut(idx).unwrap();
-            let exists = scope.variables.contains_key(var_name);
-            if !exists {
-                continue;
+    /**
+     * Closures capturing the same variable share the upvalue.
+     */
+    fn capture_upvalue(&mut self, slot: usize) -> Rc<RefCell<Upvalue>> {
+        for upvalue in &self.open_upvalues {
+            if let Upvalue::Open(open_slot) = &*upvalue.borrow() {
+                if *open_slot == slot {
+                    return upvalue.clone();
+                }
             }
-            *scope.variables.get_mut(var_name).unwrap() = val;
-            return;
         }
-        self.error(format!("No variable called '{}' exists!", var_name));
+        let upvalue = Rc::new(RefCell::new(Upvalue::Open(slot)));
+        self.open_upvalues.push(upvalue.clone());
+        upvalue
     }
 
-    fn declare_variable(&mut self, var_name: &String) {
-        assert!(self.stack.len() > 0);
+    /**
+     * Moves the variables at "from_slot" and above out of the stack into their upvalues.
+     */
+    fn close_upvalues(&mut self, from_slot: usize) {
+        let stack = &self.stack;
+        self.open_upvalues.retain(|upvalue| {
+            let slot = match &*upvalue.borrow() {
+                Upvalue::Open(slot) => *slot,
+                Upvalue::Closed(_) => { return false; }
+            };
+            if slot < from_slot {
+                return true;
+            }
+            *upvalue.borrow_mut() = Upvalue::Closed(stack[slot].clone());
+            false
+        });
+    }
 
-        let val = self.pop();
-        let scope = self.get_scope();
-        if scope.variables.contains_key(var_name) {
-            self.error(format!("A variable called '{}' already exists!", var_name));
+    fn declare_native_function(&mut self, fn_name: &String, arity: u32, func: NativeFunction) -> Result<(), GiffiError> {
+        self.declare_global(fn_name, Value::Function(Function::Native(Rc::from(fn_name.as_str()), arity, func)))
+    }
+
+    fn declare_global(&mut self, var_name: &String, value: Value) -> Result<(), GiffiError> {
+        let next_slot = self.global_names.len();
+        let slot = *self.global_names.entry(var_name.clone()).or_insert(next_slot);
+        self.globals.resize(self.global_names.len(), None);
+        if self.globals[slot].is_some() {
+            return Err(self.error(format!("A variable called '{}' already exists!", var_name)));
         }
-        scope.variables.insert(var_name.clone(), val);
+        self.globals[slot] = Some(value);
+        Ok(())
     }
 
-    fn declare_function(&mut self, fn_name: &String, fn_body: &Vec<ParserToken>) {
-        if self.get_scope_count() > 1 {
-            self.error("Function declerations only allowed in the global scope!".to_string());
+    /**
+     * Returns the value of a global variable, arrays, maps and objects are shared with the script
+     */
+    pub fn get_variable_value(&self, var_name: &String) -> Result<Value, GiffiError> {
+        if let Some(slot) = self.global_names.get(var_name) {
+            if let Some(value) = &self.globals[*slot] {
+                return Ok(value.clone());
+            }
         }
+        Err(self.error(format!("No variable called '{}' exists", var_name)))
+    }
 
-        if self.funcs.contains_key(fn_name) {
-            self.error(format!("A function named '{}' already exsts!", fn_name));
+    fn global_name(&self, slot: u32) -> String {
+        for (name, idx) in &self.global_names {
+            if *idx == slot as usize {
+                return name.clone();
+            }
         }
-        self.funcs.insert(fn_name.clone(), fn_body.clone());
+        format!("<global {}>", slot)
     }
 
-    /**
-     * Gets pushed onto stack
-     */
-    fn get_variable(&mut self, var_name: &String) {
-        for idx in 0..self.variables.len() {
-            let scope = self.variables.get(idx).unwrap();
-            let val = scope.variables.get(var_name);
-            if val.is_none() {
-                continue;
+    fn frame(&self) -> &CallFrame {
+        self.frames.last().unwrap()
+    }
+
+    fn unary_op(&mut self, op: OpCode, value: Value) -> Result<Value, GiffiError> {
+        match (op, &value) {
+            (OpCode::Not, _) => { return Ok(Value::Boolean(!value.is_true())); }
+            (OpCode::Negate, Value::Int(i)) => {
+                return i.checked_neg().map(Value::Int).ok_or_else(|| self.error_of_kind(ErrorKind::Overflow, format!("{:?} overflowed", op)));
             }
-            self.push(val.unwrap().clone());
-            return;
+            (OpCode::Negate, Value::Float(f)) => { return Ok(Value::Float(-f)); }
+            (OpCode::BitNot, Value::Int(i)) => { return Ok(Value::Int(!i)); }
+            _ => { return Err(self.error_of_kind(ErrorKind::Type, format!("Cannot apply {:?} to {:?}", op, value))); }
         }
-        self.error(format!("No variable called '{}' exists", var_name));
     }
 
-    #[must_use]
     /**
-     * Returns a COPY
+     * Operator on two Ints, None when the result doesn't fit, the divisor is 0, the shift is out of range
+     * or it isn't an Int operator. The dispatch loop uses it without going through "op".
      */
-    pub fn get_variable_value(&mut self, var_name: &String) -> Value {
-        self.get_variable(var_name);
-        return self.pop();
+    fn int_op(op: OpCode, a: i64, b: i64) -> Option<Value> {
+        match op {
+            OpCode::Add => { return a.checked_add(b).map(Value::Int); }
+            OpCode::Sub => { return a.checked_sub(b).map(Value::Int); }
+            OpCode::Mul => { return a.checked_mul(b).map(Value::Int); }
+            OpCode::Div => { return a.checked_div(b).map(Value::Int); }
+            OpCode::Modulo => { return a.checked_rem(b).map(Value::Int); }
+            OpCode::Less => { return Some(Value::Boolean(a < b)); }
+            OpCode::Greater => { return Some(Value::Boolean(a > b)); }
+            OpCode::LessEqual => { return Some(Value::Boolean(a <= b)); }
+            OpCode::GreaterEqual => { return Some(Value::Boolean(a >= b)); }
+            OpCode::Equal => { return Some(Value::Boolean(a == b)); }
+            OpCode::NotEqual => { return Some(Value::Boolean(a != b)); }
+            OpCode::BitAnd => { return Some(Value::Int(a & b)); }
+            OpCode::BitOr => { return Some(Value::Int(a | b)); }
+            OpCode::BitXor => { return Some(Value::Int(a ^ b)); }
+            OpCode::ShiftLeft if (0..64).contains(&b) => { return Some(Value::Int(a << b)); }
+            OpCode::ShiftRight if (0..64).contains(&b) => { return Some(Value::Int(a >> b)); }
+            _ => { return None; }
+        }
     }
 
-    fn get_scope_count(&self) -> usize {
-        self.variables.len()
+    fn op(&mut self, op: OpCode, lhs: Value, rhs: Value) -> Result<Value, GiffiError> {
+        if let (Value::Int(a), Value::Int(b)) = (&lhs, &rhs) {
+            let (a, b) = (*a, *b);
+            if let Some(r) = Interpreter::int_op(op, a, b) {
+                return Ok(r);
+            }
+            // Results which don't fit are an error, like "**", dividing by 0 is left to the error below
+            match op {
+                OpCode::Add | OpCode::Sub | OpCode::Mul => {
+                    return Err(self.error_of_kind(ErrorKind::Overflow, format!("{:?} overflowed", op)));
+                }
+                OpCode::Div | OpCode::Modulo if b != 0 => {
+                    return Err(self.error_of_kind(ErrorKind::Overflow, format!("{:?} overflowed", op)));
+                }
+                OpCode::ShiftLeft | OpCode::ShiftRight => {
+                    return Err(self.error(format!("Cannot shift by {}, has to be between 0 and 63", b)));
+                }
+                _ => {}
+            }
+        }
+
+        if matches!(op, OpCode::BitAnd | OpCode::BitOr | OpCode::BitXor | OpCode::ShiftLeft | OpCode::ShiftRight) {
+            return Err(self.error_of_kind(ErrorKind::Type, format!("{:?} is only defined for Ints, got {:?} and {:?}", op, lhs, rhs)));
+        }
+
+        let (lhs_type, rhs_type) = (lhs.type_name(), rhs.type_name());
+        let r = match op {
+            OpCode::Add => lhs.add(rhs),
+            OpCode::Sub => lhs.sub(rhs),
+            OpCode::Mul => lhs.mul(rhs),
+            OpCode::Div => lhs.div(rhs),
+            OpCode::Modulo => lhs.modulo(rhs),
+            OpCode::Power => lhs.pow(&rhs),
+            OpCode::Less => lhs.less_than(rhs),
+            OpCode::Greater => lhs.greater_than(rhs),
+            OpCode::LessEqual => lhs.less_equal(rhs),
+            OpCode::GreaterEqual => lhs.greater_equal(rhs),
+            OpCode::Equal => Ok(Value::Boolean(lhs == rhs)),
+            OpCode::NotEqual => Ok(Value::Boolean(lhs != rhs)),
+            _ => { return Err(self.error(format!("{:?} is not an operator", op))); }
+        };
+
+        r.map_err(|e| match e {
+            ValueE::Overflow => self.error_of_kind(ErrorKind::Overflow, format!("{:?} overflowed", op)),
+            ValueE::DivisionByZero => self.error_of_kind(ErrorKind::DivisionByZero, "Division by zero".to_string()),
+            _ => self.error_of_kind(ErrorKind::Type, format!("Cannot apply {:?} to {} and {}", op, lhs_type, rhs_type)),
+        })
     }
 
-    fn get_scope(&mut self) -> &mut Scope {
-        self.variables.front_mut().unwrap()
+    pub fn push(&mut self, val: Value) {
+        self.stack.push(val);
     }
 
-    fn get_scope_type(&self) -> &ScopeType {
-        &self.variables.front().unwrap().scope_type
+    /**
+     * For the VM's own instructions, the compiler keeps the stack balanced so there's always a value to pop.
+     */
+    fn pop_value(&mut self) -> Value {
+        return self.stack.pop().expect("The stack is empty");
     }
 
-    fn start_scope(&mut self, scope_name: String, scope_type: ScopeType) {
-        self.variables.push_front(
-            Scope::new(scope_name, scope_type)
-        );
+    pub fn pop(&mut self) -> Result<Value, GiffiError> {
+        if self.stack.len() == 0 {
+            return Err(self.error(format!("not enough arguments to pop")));
+        }
+        return Ok(self.stack.pop().unwrap());
     }
 
-    fn end_scope(&mut self) {
-        self.variables.pop_front();
+    pub fn pop_int(&mut self) -> Result<i64, GiffiError> {
+        match self.pop()? {
+            Value::Int(i) => Ok(i),
+            value => Err(self.error_of_kind(ErrorKind::Type, format!("Expected an Int got {:?} instead!", value))),
+        }
     }
 
-    fn op(&mut self, op: &String) {
-        assert!(self.stack.len() >= 2, "not enough arguments to do an operation");
+    pub fn pop_float(&mut self) -> Result<f64, GiffiError> {
+        match self.pop()? {
+            Value::Float(f) => Ok(f),
+            value => Err(self.error_of_kind(ErrorKind::Type, format!("Expected a Float got {:?} instead!", value))),
+        }
+    }
 
-        let lhs = self.pop();
-        let rhs = self.pop();
-        let r = lhs.do_operation(op, rhs);
+    pub fn pop_literal(&mut self) -> Result<String, GiffiError> {
+        match self.pop()? {
+            Value::Literal(s) => Ok(s),
+            value => Err(self.error_of_kind(ErrorKind::Type, format!("Expected a String Literal got {:?} instead!", value))),
+        }
+    }
 
-        if r.is_err() {
-            self.error(format!("Error when executing an operator {:?}", r.unwrap_err()));
+    pub fn pop_char(&mut self) -> Result<char, GiffiError> {
+        match self.pop()? {
+            Value::Char(c) => Ok(c),
+            value => Err(self.error_of_kind(ErrorKind::Type, format!("Expected a Char got {:?} instead!", value))),
         }
-        let ur = r.unwrap();
+    }
 
-        self.push(ur.clone());
+    /**
+     * The bytes are shared with the script, changes to them are visible there
+     */
+    pub fn pop_bytes(&mut self) -> Result<Rc<RefCell<Vec<u8>>>, GiffiError> {
+        match self.pop()? {
+            Value::Bytes(bytes) => Ok(bytes),
+            value => Err(self.error_of_kind(ErrorKind::Type, format!("Expected Bytes got {:?} instead!", value))),
+        }
     }
 
-    pub fn push(&mut self, val: Value) {
-        self.stack.push(val);
+    pub fn pop_ptr(&mut self) -> Result<*mut u32, GiffiError> {
+        match self.pop()? {
+            Value::Ptr(ptr) => Ok(ptr),
+            value => Err(self.error_of_kind(ErrorKind::Type, format!("Expected a Pointer got {:?} instead!", value))),
+        }
     }
 
-    pub fn pop(&mut self) -> Value {
-        if self.stack.len() == 0 {
-            self.error(format!("not enough arguments to pop"));
+    /**
+     * Source position of the instruction being executed
+     */
+    fn current_span(&self) -> Span {
+        if let Some(frame) = self.frames.last() {
+            let spans = &frame.closure.proto.chunk.spans;
+            if frame.ip > 0 && frame.ip <= spans.len() {
+                return spans[frame.ip - 1];
+            }
         }
-        return self.stack.pop().unwrap();
+        Span::default()
     }
 
-    fn error(&self, error_msg: String) -> ! {
-        use colored::Colorize;
+    /**
+     * Creates a runtime error pointing at the instruction being executed
+     */
+    pub fn error(&self, error_msg: String) -> GiffiError {
+        GiffiError::new(ErrorKind::Runtime, error_msg, self.current_span())
+    }
 
-        println!("{}", format!("------Interpreter Panic!-----").red().bold());
-        println!("{}", format!("-------VALUE STACK [{}]:------", self.stack.len()).red().bold());
+    /**
+     * Runtime error of a more specific kind, e.g. ErrorKind::DivisionByZero
+     */
+    pub fn error_of_kind(&self, kind: ErrorKind, error_msg: String) -> GiffiError {
+        GiffiError::new(kind, error_msg, self.current_span())
+    }
 
-        let mut stack_copy = self.stack.clone();
-        stack_copy.reverse();
+}
 
-        for idx in (0..stack_copy.len()).rev() {
-            let val = stack_copy.get(idx).unwrap();
-            println!("[{}] = {}",
-                idx, 
-                format!("{:?}", val).green()
-            );
+fn panic(interpreter: *mut Interpreter) -> Result<(), GiffiError> {
+    let machine = unsafe { interpreter.as_mut().unwrap() };
+    let value = machine.pop()?;
+    return Err(machine.error(value.to_string()));
+}
 
+/**
+ * Keys of a map in insertion order
+ */
+fn keys(interpreter: *mut Interpreter) -> Result<(), GiffiError> {
+    let machine = unsafe { interpreter.as_mut().unwrap() };
+    match machine.pop()? {
+        Value::Map(map) => {
+            let keys = map.borrow().keys().into_iter().map(Value::Literal).collect();
+            machine.push(Value::array(keys));
         }
+        value => { return Err(machine.error_of_kind(ErrorKind::Type, format!("Expected a Map got {:?} instead!", value))); }
+    }
+    Ok(())
+}
 
-        println!("{}", format!("----------VARIABLES:---------").red().bold());
-        for scope_idx in (0..self.variables.len()).rev() {
-            let identation = self.variables.len()-scope_idx;
-            let scope_name = &self.variables[scope_idx].scope_name;
-            for _ in 0..identation {
-                print!(" ");
-            }
+/**
+ * Arrays, maps and objects are passed around by reference, "copy(value)" duplicates them (and everything inside of them)
+ */
+fn copy(interpreter: *mut Interpreter) -> Result<(), GiffiError> {
+    let machine = unsafe { interpreter.as_mut().unwrap() };
+    let value = machine.pop()?;
+    machine.push(value.deep_copy());
+    Ok(())
+}
+
+/**
+ * Unicode code point of a char, "ord('a')" is 97
+ */
+fn ord(interpreter: *mut Interpreter) -> Result<(), GiffiError> {
+    let machine = unsafe { interpreter.as_mut().unwrap() };
+    let c = machine.pop_char()?;
+    machine.push(Value::Int(c as i64));
+    Ok(())
+}
 
-            println!("{}:", scope_name);
-            for (var_name, value) in &self.variables[scope_idx].variables {
-                for _ in 0..identation {
-                    print!(" ");
+/**
+ * Char of a unicode code point, "chr(97)" is 'a'
+ */
+fn chr(interpreter: *mut Interpreter) -> Result<(), GiffiError> {
+    let machine = unsafe { interpreter.as_mut().unwrap() };
+    let code = machine.pop_int()?;
+    match u32::try_from(code).ok().and_then(char::from_u32) {
+        Some(c) => { machine.push(Value::Char(c)); }
+        None => { return Err(machine.error(format!("{} is not a valid unicode code point", code))); }
+    }
+    Ok(())
+}
+
+/**
+ * "bytes(4)" is 4 zeroed bytes, "bytes("hi")" the UTF-8 encoding of a string, "bytes([1, 2])" the bytes in an array
+ */
+fn bytes(interpreter: *mut Interpreter) -> Result<(), GiffiError> {
+    let machine = unsafe { interpreter.as_mut().unwrap() };
+    match machine.pop()? {
+        Value::Int(size) => {
+            let Ok(size) = usize::try_from(size) else {
+                return Err(machine.error(format!("Bytes cannot have a negative size, got {}!", size)));
+            };
+            // The size comes from the script, failing to allocate it has to be an error and not an abort
+            let mut bytes = vec![];
+            if bytes.try_reserve_exact(size).is_err() {
+                return Err(machine.error(format!("Cannot allocate {} bytes!", size)));
+            }
+            bytes.resize(size, 0);
+            machine.push(Value::bytes(bytes));
+        }
+        Value::Literal(string) => {
+            machine.push(Value::bytes(string.into_bytes()));
+        }
+        Value::Array(array) => {
+            let mut bytes = vec![];
+            for element in array.borrow().iter() {
+                match element {
+                    Value::Int(byte) if (0..=255).contains(byte) => { bytes.push(*byte as u8); }
+                    _ => { return Err(machine.error(format!("A byte has to be an Int between 0 and 255, got {:?}", element))); }
                 }
-                println!(" {} = {:?}", var_name, value);
             }
+            machine.push(Value::bytes(bytes));
         }
-
-        println!("{}", format!("-----------------------------").red().bold());
-        let op = unsafe { self.last_op.as_ref() };
-        println!("{}", format!("Last operation: {:#?}", op.unwrap_or(&ParserToken::Nop)).red());
-        println!("{}", format!("Interpreter Error: '{}'", error_msg.bold()).red());
-        println!("{}", format!("-----------------------------").red().bold());
-
-        exit(-1);
+        value => { return Err(machine.error_of_kind(ErrorKind::Type, format!("Expected an Int, a String or an Array got {:?} instead!", value))); }
     }
-
+    Ok(())
 }
-
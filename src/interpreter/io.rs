@@ -1,49 +1,77 @@
-use crate::parser::{Parser, ParserToken};
+use crate::error::{GiffiError, ErrorKind};
 use crate::interpreter::{Interpreter};
 use crate::value::{Value};
 
-pub fn import_libs(interpreter: &mut Interpreter) {
-    interpreter.declare_function(&"print".to_string(), &vec![
-        ParserToken::CallNative(print)
-    ]);
-    interpreter.declare_function(&"delay_ms".to_string(), &vec![
-        ParserToken::CallNative(delay_ms)
-    ]);
-    interpreter.declare_function(&"delay_s".to_string(), &vec![
-        ParserToken::CallNative(delay_s)
-    ]);
+pub fn import_libs(interpreter: &mut Interpreter) -> Result<(), GiffiError> {
+    interpreter.declare_native_function(&"print".to_string(), 1, print)?;
+    interpreter.declare_native_function(&"delay_ms".to_string(), 1, delay_ms)?;
+    interpreter.declare_native_function(&"delay_s".to_string(), 1, delay_s)?;
+    interpreter.declare_native_function(&"read_bytes".to_string(), 1, read_bytes)?;
+    interpreter.declare_native_function(&"write_bytes".to_string(), 2, write_bytes)?;
+    Ok(())
 }
 
-fn print(interpreter: *mut Interpreter) {
+fn print(interpreter: *mut Interpreter) -> Result<(), GiffiError> {
     let machine = unsafe { interpreter.as_mut().unwrap() };
-    let val = machine.pop();
-    println!("{}", val.to_string());
+    let val = machine.pop()?;
+    println!("{}", val);
+    Ok(())
 }
 
-fn delay_ms(interpreter: *mut Interpreter) {
+fn delay_ms(interpreter: *mut Interpreter) -> Result<(), GiffiError> {
     let machine = unsafe { interpreter.as_mut().unwrap() };
 
-    let val = machine.pop();
+    let val = machine.pop()?;
     if let Value::Int(ms) = val {
         if ms.is_negative() {
-            machine.error(format!("Int cannot be a negative value, got {}!", ms));
+            return Err(machine.error(format!("Int cannot be a negative value, got {}!", ms)));
         }
         use std::{thread, time};
         let millis = time::Duration::from_millis(ms as u64);
         thread::sleep(millis);
     }
+    Ok(())
 }
 
-fn delay_s(interpreter: *mut Interpreter) {
+fn delay_s(interpreter: *mut Interpreter) -> Result<(), GiffiError> {
     let machine = unsafe { interpreter.as_mut().unwrap() };
 
-    let val = machine.pop();
+    let val = machine.pop()?;
     if let Value::Int(ms) = val {
         if ms.is_negative() {
-            machine.error(format!("Int cannot be a negative value, got {}!", ms));
+            return Err(machine.error(format!("Int cannot be a negative value, got {}!", ms)));
         }
         use std::{thread, time};
         let millis = time::Duration::from_secs(ms as u64);
         thread::sleep(millis);
     }
+    Ok(())
+}
+
+/**
+ * "read_bytes(path)", the whole file as Bytes
+ */
+fn read_bytes(interpreter: *mut Interpreter) -> Result<(), GiffiError> {
+    let machine = unsafe { interpreter.as_mut().unwrap() };
+
+    let path = machine.pop_literal()?;
+    match std::fs::read(&path) {
+        Ok(bytes) => { machine.push(Value::bytes(bytes)); }
+        Err(e) => { return Err(machine.error_of_kind(ErrorKind::Io, format!("Cannot read '{}': {}", path, e))); }
+    }
+    Ok(())
+}
+
+/**
+ * "write_bytes(path, bytes)", creates the file or replaces its contents
+ */
+fn write_bytes(interpreter: *mut Interpreter) -> Result<(), GiffiError> {
+    let machine = unsafe { interpreter.as_mut().unwrap() };
+
+    let bytes = machine.pop_bytes()?;
+    let path = machine.pop_literal()?;
+    if let Err(e) = std::fs::write(&path, &*bytes.borrow()) {
+        return Err(machine.error_of_kind(ErrorKind::Io, format!("Cannot write '{}': {}", path, e)));
+    }
+    Ok(())
 }
@@ -1,133 +1,138 @@
 use std::collections::hash_map::RandomState;
 
 use crate::lexer::{Lexer};
-use crate::parser::{Parser, ParserToken};
+use crate::error::{GiffiError, ErrorKind};
 use crate::interpreter::{Interpreter};
-use crate::value::{ValueAdder, Value};
-
-pub fn import_libs(interpreter: &mut Interpreter) {
-    interpreter.declare_function(&"sum".to_string(), &vec![
-        ParserToken::CallNative(sum)
-    ]);
-    interpreter.declare_function(&"abs".to_string(), &vec![
-        ParserToken::CallNative(abs)
-    ]);
-    interpreter.declare_function(&"max".to_string(), &vec![
-        ParserToken::CallNative(max)
-    ]);
-    interpreter.declare_function(&"min".to_string(), &vec![
-        ParserToken::CallNative(min)
-    ]);
-    interpreter.declare_function(&"rand_rangei".to_string(), &vec![
-        ParserToken::CallNative(rand_rangei)
-    ]);
-    interpreter.declare_function(&"rand_rangef".to_string(), &vec![
-        ParserToken::CallNative(rand_rangef)
-    ]);
-
-    interpreter.declare_function(&"sin".to_string(), &vec![
-        ParserToken::CallNative(sin)
-    ]);
-    interpreter.declare_function(&"tan".to_string(), &vec![
-        ParserToken::CallNative(tan)
-    ]);
-    interpreter.declare_function(&"cos".to_string(), &vec![
-        ParserToken::CallNative(cos)
-    ]);
-    interpreter.declare_function(&"sinh".to_string(), &vec![
-        ParserToken::CallNative(sinh)
-    ]);
-    interpreter.declare_function(&"tanh".to_string(), &vec![
-        ParserToken::CallNative(tanh)
-    ]);
-    interpreter.declare_function(&"cosh".to_string(), &vec![
-        ParserToken::CallNative(cosh)
-    ]);
+use crate::value::{ValueAdder, Value, ValueE};
+
+pub fn import_libs(interpreter: &mut Interpreter) -> Result<(), GiffiError> {
+    interpreter.declare_native_function(&"sum".to_string(), 2, sum)?;
+    interpreter.declare_native_function(&"abs".to_string(), 1, abs)?;
+    interpreter.declare_native_function(&"max".to_string(), 2, max)?;
+    interpreter.declare_native_function(&"min".to_string(), 2, min)?;
+    interpreter.declare_native_function(&"rand_rangei".to_string(), 2, rand_rangei)?;
+    interpreter.declare_native_function(&"rand_rangef".to_string(), 2, rand_rangef)?;
+
+    interpreter.declare_native_function(&"sin".to_string(), 1, sin)?;
+    interpreter.declare_native_function(&"tan".to_string(), 1, tan)?;
+    interpreter.declare_native_function(&"cos".to_string(), 1, cos)?;
+    interpreter.declare_native_function(&"sinh".to_string(), 1, sinh)?;
+    interpreter.declare_native_function(&"tanh".to_string(), 1, tanh)?;
+    interpreter.declare_native_function(&"cosh".to_string(), 1, cosh)?;
+    Ok(())
 }
 
-fn sum(interpreter: *mut Interpreter) {
+fn sum(interpreter: *mut Interpreter) -> Result<(), GiffiError> {
     let machine = unsafe { interpreter.as_mut() }.unwrap();
-    let arg2 = machine.pop();
-    let arg1 = machine.pop();
-    machine.push(arg1.add(arg2).unwrap());
+    let arg2 = machine.pop()?;
+    let arg1 = machine.pop()?;
+    let r = arg1.add(arg2);
+    if let Err(ValueE::Overflow) = r {
+        return Err(machine.error_of_kind(ErrorKind::Overflow, "sum overflowed".to_string()));
+    }
+    if let Err(e) = r {
+        return Err(machine.error(format!("Cannot sum the arguments {:?}", e)));
+    }
+    machine.push(r.unwrap());
+    Ok(())
 }
 
-fn abs(interpreter: *mut Interpreter) {
+fn abs(interpreter: *mut Interpreter) -> Result<(), GiffiError> {
     let machine = unsafe { interpreter.as_mut() }.unwrap();
-    let arg = machine.pop().int();
-    machine.push(Value::Int(arg.abs()));
+    let arg = machine.pop_int()?;
+    let Some(r) = arg.checked_abs() else {
+        return Err(machine.error_of_kind(ErrorKind::Overflow, format!("abs({}) overflowed", arg)));
+    };
+    machine.push(Value::Int(r));
+    Ok(())
 }
 
-fn max(interpreter: *mut Interpreter) {
+fn max(interpreter: *mut Interpreter) -> Result<(), GiffiError> {
     let machine = unsafe { interpreter.as_mut() }.unwrap();
-    let arg1 = machine.pop().int();
-    let arg2 = machine.pop().int();
+    let arg1 = machine.pop_int()?;
+    let arg2 = machine.pop_int()?;
     let r = arg1.max(arg2);
     machine.push(Value::Int(r));
+    Ok(())
 }
 
-fn min(interpreter: *mut Interpreter) {
+fn min(interpreter: *mut Interpreter) -> Result<(), GiffiError> {
     let machine = unsafe { interpreter.as_mut() }.unwrap();
-    let arg1 = machine.pop().int();
-    let arg2 = machine.pop().int();
+    let arg1 = machine.pop_int()?;
+    let arg2 = machine.pop_int()?;
     let r = arg1.min(arg2);
     machine.push(Value::Int(r));
+    Ok(())
 }
 
-fn sin(interpreter: *mut Interpreter) {
+fn sin(interpreter: *mut Interpreter) -> Result<(), GiffiError> {
     let machine = unsafe { interpreter.as_mut() }.unwrap();
-    let arg = machine.pop().float();
+    let arg = machine.pop_float()?;
     machine.push(Value::Float(arg.sin()));
+    Ok(())
 }
 
-fn tan(interpreter: *mut Interpreter) {
+fn tan(interpreter: *mut Interpreter) -> Result<(), GiffiError> {
     let machine = unsafe { interpreter.as_mut() }.unwrap();
-    let arg = machine.pop().float();
+    let arg = machine.pop_float()?;
     machine.push(Value::Float(arg.tan()));
+    Ok(())
 }
 
-fn cos(interpreter: *mut Interpreter) {
+fn cos(interpreter: *mut Interpreter) -> Result<(), GiffiError> {
     let machine = unsafe { interpreter.as_mut() }.unwrap();
-    let arg = machine.pop().float();
+    let arg = machine.pop_float()?;
     machine.push(Value::Float(arg.cos()));
+    Ok(())
 }
 
-fn sinh(interpreter: *mut Interpreter) {
+fn sinh(interpreter: *mut Interpreter) -> Result<(), GiffiError> {
     let machine = unsafe { interpreter.as_mut() }.unwrap();
-    let arg = machine.pop().float();
+    let arg = machine.pop_float()?;
     machine.push(Value::Float(arg.sinh()));
+    Ok(())
 }
 
-fn tanh(interpreter: *mut Interpreter) {
+fn tanh(interpreter: *mut Interpreter) -> Result<(), GiffiError> {
     let machine = unsafe { interpreter.as_mut() }.unwrap();
-    let arg = machine.pop().float();
+    let arg = machine.pop_float()?;
     machine.push(Value::Float(arg.tanh()));
+    Ok(())
 }
 
-fn cosh(interpreter: *mut Interpreter) {
+fn cosh(interpreter: *mut Interpreter) -> Result<(), GiffiError> {
     let machine = unsafe { interpreter.as_mut() }.unwrap();
-    let arg = machine.pop().float();
+    let arg = machine.pop_float()?;
     machine.push(Value::Float(arg.cosh()));
+    Ok(())
 }
 
-fn rand_rangei(interpreter: *mut Interpreter) {
+fn rand_rangei(interpreter: *mut Interpreter) -> Result<(), GiffiError> {
     use rand::Rng;
 
     let machine = unsafe { interpreter.as_mut() }.unwrap();
-    let arg2 = machine.pop().int();
-    let arg1 = machine.pop().int();
+    let arg2 = machine.pop_int()?;
+    let arg1 = machine.pop_int()?;
+    if arg1 >= arg2 {
+        return Err(machine.error(format!("Empty range {}..{}", arg1, arg2)));
+    }
     let r = rand::thread_rng().gen_range(arg1..arg2);
     machine.push(Value::Int(r));
+    Ok(())
 }
 
-fn rand_rangef(interpreter: *mut Interpreter) {
+fn rand_rangef(interpreter: *mut Interpreter) -> Result<(), GiffiError> {
     use rand::Rng;
 
     let machine = unsafe { interpreter.as_mut() }.unwrap();
-    let arg2 = machine.pop().float();
-    let arg1 = machine.pop().float();
+    let arg2 = machine.pop_float()?;
+    let arg1 = machine.pop_float()?;
+    if arg1 >= arg2 {
+        return Err(machine.error(format!("Empty range {}..{}", arg1, arg2)));
+    }
     let r = rand::thread_rng().gen_range(arg1..arg2);
     machine.push(Value::Float(r));
+    Ok(())
 }
 
 
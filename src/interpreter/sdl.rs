@@ -1,83 +1,62 @@
 use std::ffi::{CString, c_char};
 use sdl2_sys::{SDL_CreateRenderer, SDL_CreateWindow, SDL_RendererFlags, SDL_WindowFlags, SDL_WINDOWPOS_CENTERED_MASK, SDL_QuitEvent};
 
-use crate::parser::{Parser, ParserToken};
+use crate::error::{GiffiError, ErrorKind};
 use crate::interpreter::{Interpreter};
 use crate::value::{Value};
 
 
-pub fn import_libs(interpreter: &mut Interpreter) {
-    interpreter.declare_function(&"sdl_init".to_string(), &vec![
-        ParserToken::CallNative(sdl_init)
-    ]);
-    interpreter.declare_function(&"sdl_quit".to_string(), &vec![
-        ParserToken::CallNative(sdl_quit)
-    ]);
+pub fn import_libs(interpreter: &mut Interpreter) -> Result<(), GiffiError> {
+    interpreter.declare_native_function(&"sdl_init".to_string(), 0, sdl_init)?;
+    interpreter.declare_native_function(&"sdl_quit".to_string(), 0, sdl_quit)?;
 
     // Window stuff
-    interpreter.declare_function(&"create_window".to_string(), &vec![
-        ParserToken::CallNative(create_window)
-    ]);
-    interpreter.declare_function(&"destroy_window".to_string(), &vec![
-        ParserToken::CallNative(destroy_window)
-    ]);
+    interpreter.declare_native_function(&"create_window".to_string(), 3, create_window)?;
+    interpreter.declare_native_function(&"destroy_window".to_string(), 1, destroy_window)?;
 
     // Utils
-    interpreter.declare_function(&"do_events".to_string(), &vec![
-        ParserToken::CallNative(do_events)
-    ]);
-    interpreter.declare_function(&"get_key_scancode".to_string(), &vec![
-        ParserToken::CallNative(get_key_scancode)
-    ]);
-    interpreter.declare_function(&"is_key_down".to_string(), &vec![
-        ParserToken::CallNative(is_key_down)
-    ]);
+    interpreter.declare_native_function(&"do_events".to_string(), 0, do_events)?;
+    interpreter.declare_native_function(&"get_key_scancode".to_string(), 1, get_key_scancode)?;
+    interpreter.declare_native_function(&"is_key_down".to_string(), 1, is_key_down)?;
 
     // Renderer stuff
-    interpreter.declare_function(&"destroy_renderer".to_string(), &vec![
-        ParserToken::CallNative(destroy_renderer)
-    ]);
-    interpreter.declare_function(&"create_renderer".to_string(), &vec![
-        ParserToken::CallNative(create_renderer)
-    ]);
-    interpreter.declare_function(&"render_present".to_string(), &vec![
-        ParserToken::CallNative(render_present)
-    ]);
-    interpreter.declare_function(&"render_clear".to_string(), &vec![
-        ParserToken::CallNative(render_clear)
-    ]);
-    interpreter.declare_function(&"set_render_draw_color".to_string(), &vec![
-        ParserToken::CallNative(set_render_draw_color)
-    ]);
+    interpreter.declare_native_function(&"destroy_renderer".to_string(), 1, destroy_renderer)?;
+    interpreter.declare_native_function(&"create_renderer".to_string(), 1, create_renderer)?;
+    interpreter.declare_native_function(&"render_present".to_string(), 1, render_present)?;
+    interpreter.declare_native_function(&"render_clear".to_string(), 1, render_clear)?;
+    interpreter.declare_native_function(&"set_render_draw_color".to_string(), 5, set_render_draw_color)?;
 
     // Draw Funcs 
-    interpreter.declare_function(&"render_draw_rect".to_string(), &vec![
-        ParserToken::CallNative(render_draw_rect)
-    ]);
-    interpreter.declare_function(&"render_fill_rect".to_string(), &vec![
-        ParserToken::CallNative(render_fill_rect)
-    ]);
-    interpreter.declare_function(&"render_draw_point".to_string(), &vec![
-        ParserToken::CallNative(render_draw_point)
-    ]);
+    interpreter.declare_native_function(&"render_draw_rect".to_string(), 5, render_draw_rect)?;
+    interpreter.declare_native_function(&"render_fill_rect".to_string(), 5, render_fill_rect)?;
+    interpreter.declare_native_function(&"render_draw_point".to_string(), 3, render_draw_point)?;
+
+    // Texture stuff, the pixels come from a Bytes buffer with 4 bytes (RGBA) per pixel
+    interpreter.declare_native_function(&"create_texture".to_string(), 3, create_texture)?;
+    interpreter.declare_native_function(&"destroy_texture".to_string(), 1, destroy_texture)?;
+    interpreter.declare_native_function(&"update_texture".to_string(), 2, update_texture)?;
+    interpreter.declare_native_function(&"render_copy".to_string(), 2, render_copy)?;
+    Ok(())
 }
 
-fn sdl_init(_: *mut Interpreter) {
+fn sdl_init(_: *mut Interpreter) -> Result<(), GiffiError> {
     unsafe {
-        sdl2_sys::SDL_Init(sdl2_sys::SDL_INIT_EVERYTHING as u32);
+        sdl2_sys::SDL_Init(sdl2_sys::SDL_INIT_EVERYTHING);
     }
+    Ok(())
 }
 
-fn sdl_quit(_: *mut Interpreter) {
+fn sdl_quit(_: *mut Interpreter) -> Result<(), GiffiError> {
     unsafe {
         sdl2_sys::SDL_Quit();
     }
+    Ok(())
 }
 
-fn create_renderer(interpreter: *mut Interpreter) {
+fn create_renderer(interpreter: *mut Interpreter) -> Result<(), GiffiError> {
     let machine = unsafe { interpreter.as_mut().unwrap() };
 
-    let window_ptr = machine.pop().ptr() as *mut sdl2_sys::SDL_Window;
+    let window_ptr = machine.pop_ptr()? as *mut sdl2_sys::SDL_Window;
     
     // SDL2 Calls
     let renderer = unsafe { SDL_CreateRenderer(
@@ -86,27 +65,29 @@ fn create_renderer(interpreter: *mut Interpreter) {
 
     // Push
     machine.push(Value::Ptr(renderer as *mut u32));
+    Ok(())
 }
 
-fn destroy_renderer(interpreter: *mut Interpreter) {
+fn destroy_renderer(interpreter: *mut Interpreter) -> Result<(), GiffiError> {
     let machine = unsafe { interpreter.as_mut().unwrap() };
 
-    let renderer_ptr = machine.pop().ptr() as *mut sdl2_sys::SDL_Renderer;
+    let renderer_ptr = machine.pop_ptr()? as *mut sdl2_sys::SDL_Renderer;
 
     // SDL2 Calls
     unsafe { 
         sdl2_sys::SDL_DestroyRenderer(renderer_ptr)
     };
+    Ok(())
 }
 
 
-fn create_window(interpreter: *mut Interpreter) {
+fn create_window(interpreter: *mut Interpreter) -> Result<(), GiffiError> {
     let machine = unsafe { interpreter.as_mut().unwrap() };
 
     // Get args
-    let height = machine.pop().int() as i32;
-    let width = machine.pop().int() as i32;
-    let window_title = machine.pop().literal();
+    let height = machine.pop_int()? as i32;
+    let width = machine.pop_int()? as i32;
+    let window_title = machine.pop_literal()?;
     
     let title_cstring = CString::new(window_title).unwrap();
     let title_ptr: *const c_char = title_cstring.as_ptr() as *const c_char;
@@ -122,35 +103,34 @@ fn create_window(interpreter: *mut Interpreter) {
     };
 
     machine.push(Value::Ptr(window as *mut u32));
+    Ok(())
 }
 
-fn destroy_window(interpreter: *mut Interpreter) {
+fn destroy_window(interpreter: *mut Interpreter) -> Result<(), GiffiError> {
     let machine = unsafe { interpreter.as_mut().unwrap() };
 
-    let window_ptr = machine.pop().ptr() as *mut sdl2_sys::SDL_Window;
+    let window_ptr = machine.pop_ptr()? as *mut sdl2_sys::SDL_Window;
 
     // SDL2 Calls
     unsafe { 
         sdl2_sys::SDL_DestroyWindow(window_ptr)
     };
+    Ok(())
 }
 
-fn do_events(_: *mut Interpreter) {
+fn do_events(_: *mut Interpreter) -> Result<(), GiffiError> {
     // SDL2 Calls
     let layout = std::alloc::Layout::new::<sdl2_sys::SDL_Event>();
     let events = unsafe { std::alloc::alloc(layout) as *mut sdl2_sys::SDL_Event } ;
     unsafe { 
-        while sdl2_sys::SDL_PollEvent(events) != 0 {
-            match events {
-                _ => {}
-            }
-        }
+        while sdl2_sys::SDL_PollEvent(events) != 0 {}
     };
+    Ok(())
 }
 
-fn get_key_scancode(interpreter: *mut Interpreter) {
+fn get_key_scancode(interpreter: *mut Interpreter) -> Result<(), GiffiError> {
     let machine = unsafe { interpreter.as_mut().unwrap() };
-    let key_name = machine.pop().literal();
+    let key_name = machine.pop_literal()?;
 
     // SDL2 Calls
     let key_cstring = CString::new(key_name).unwrap();
@@ -160,12 +140,13 @@ fn get_key_scancode(interpreter: *mut Interpreter) {
         sdl2_sys::SDL_GetScancodeFromName(key_ptr)
     };
     machine.push(Value::Int(scancode as i64));
+    Ok(())
 }
 
-fn is_key_down(interpreter: *mut Interpreter) {
+fn is_key_down(interpreter: *mut Interpreter) -> Result<(), GiffiError> {
     let machine = unsafe { interpreter.as_mut().unwrap() };
 
-    let scancode = machine.pop().int() as usize;
+    let scancode = machine.pop_int()? as usize;
 
     // SDL2 Calls
     use core::ptr::null_mut;
@@ -176,54 +157,58 @@ fn is_key_down(interpreter: *mut Interpreter) {
     };
 
     machine.push(Value::Boolean(is_down));
+    Ok(())
 }
 
-fn set_render_draw_color(interpreter: *mut Interpreter) {
+fn set_render_draw_color(interpreter: *mut Interpreter) -> Result<(), GiffiError> {
     let machine = unsafe { interpreter.as_mut().unwrap() };
 
-    let a = machine.pop().int() as u8;
-    let b = machine.pop().int() as u8;
-    let g = machine.pop().int() as u8;
-    let r = machine.pop().int() as u8;
-    let renderer = machine.pop().ptr() as *mut sdl2_sys::SDL_Renderer;
+    let a = machine.pop_int()? as u8;
+    let b = machine.pop_int()? as u8;
+    let g = machine.pop_int()? as u8;
+    let r = machine.pop_int()? as u8;
+    let renderer = machine.pop_ptr()? as *mut sdl2_sys::SDL_Renderer;
 
     // SDL2 Calls
     unsafe {
         sdl2_sys::SDL_SetRenderDrawColor(renderer, r, g, b, a);
     }
+    Ok(())
 }
 
 
-fn render_clear(interpreter: *mut Interpreter) {
+fn render_clear(interpreter: *mut Interpreter) -> Result<(), GiffiError> {
     let machine = unsafe { interpreter.as_mut().unwrap() };
 
-    let renderer = machine.pop().ptr() as *mut sdl2_sys::SDL_Renderer;
+    let renderer = machine.pop_ptr()? as *mut sdl2_sys::SDL_Renderer;
 
     // SDL2 Calls
     unsafe {
         sdl2_sys::SDL_RenderClear(renderer);
     }
+    Ok(())
 }
 
-fn render_present(interpreter: *mut Interpreter) {
+fn render_present(interpreter: *mut Interpreter) -> Result<(), GiffiError> {
     let machine = unsafe { interpreter.as_mut().unwrap() };
 
-    let renderer = machine.pop().ptr() as *mut sdl2_sys::SDL_Renderer;
+    let renderer = machine.pop_ptr()? as *mut sdl2_sys::SDL_Renderer;
 
     // SDL2 Calls
     unsafe {
         sdl2_sys::SDL_RenderPresent(renderer);
     }
+    Ok(())
 }
 
-fn render_draw_rect(interpreter: *mut Interpreter) {
+fn render_draw_rect(interpreter: *mut Interpreter) -> Result<(), GiffiError> {
     let machine = unsafe { interpreter.as_mut().unwrap() };
 
-    let h = machine.pop().int() as i32;
-    let w = machine.pop().int() as i32;
-    let y = machine.pop().int() as i32;
-    let x = machine.pop().int() as i32;
-    let renderer = machine.pop().ptr() as *mut sdl2_sys::SDL_Renderer;
+    let h = machine.pop_int()? as i32;
+    let w = machine.pop_int()? as i32;
+    let y = machine.pop_int()? as i32;
+    let x = machine.pop_int()? as i32;
+    let renderer = machine.pop_ptr()? as *mut sdl2_sys::SDL_Renderer;
 
     let rect = sdl2_sys::SDL_Rect{
         x: x,
@@ -236,16 +221,17 @@ fn render_draw_rect(interpreter: *mut Interpreter) {
     unsafe {
         sdl2_sys::SDL_RenderDrawRect(renderer, &rect);
     }
+    Ok(())
 }
 
-fn render_fill_rect(interpreter: *mut Interpreter) {
+fn render_fill_rect(interpreter: *mut Interpreter) -> Result<(), GiffiError> {
     let machine = unsafe { interpreter.as_mut().unwrap() };
 
-    let h = machine.pop().int() as i32;
-    let w = machine.pop().int() as i32;
-    let y = machine.pop().int() as i32;
-    let x = machine.pop().int() as i32;
-    let renderer = machine.pop().ptr() as *mut sdl2_sys::SDL_Renderer;
+    let h = machine.pop_int()? as i32;
+    let w = machine.pop_int()? as i32;
+    let y = machine.pop_int()? as i32;
+    let x = machine.pop_int()? as i32;
+    let renderer = machine.pop_ptr()? as *mut sdl2_sys::SDL_Renderer;
 
     let rect = sdl2_sys::SDL_Rect{
         x: x,
@@ -258,18 +244,95 @@ fn render_fill_rect(interpreter: *mut Interpreter) {
     unsafe {
         sdl2_sys::SDL_RenderFillRect(renderer, &rect);
     }
+    Ok(())
 }
 
-fn render_draw_point(interpreter: *mut Interpreter) {
+fn render_draw_point(interpreter: *mut Interpreter) -> Result<(), GiffiError> {
     let machine = unsafe { interpreter.as_mut().unwrap() };
 
-    let y = machine.pop().int() as i32;
-    let x = machine.pop().int() as i32;
-    let renderer = machine.pop().ptr() as *mut sdl2_sys::SDL_Renderer;
+    let y = machine.pop_int()? as i32;
+    let x = machine.pop_int()? as i32;
+    let renderer = machine.pop_ptr()? as *mut sdl2_sys::SDL_Renderer;
 
     // SDL2 Calls
     unsafe {
         sdl2_sys::SDL_RenderDrawPoint(renderer, x, y);
     }
+    Ok(())
 }
 
+fn create_texture(interpreter: *mut Interpreter) -> Result<(), GiffiError> {
+    let machine = unsafe { interpreter.as_mut().unwrap() };
+
+    let height = machine.pop_int()? as i32;
+    let width = machine.pop_int()? as i32;
+    let renderer = machine.pop_ptr()? as *mut sdl2_sys::SDL_Renderer;
+
+    // SDL2 Calls
+    let texture = unsafe {
+        sdl2_sys::SDL_CreateTexture(
+            renderer,
+            sdl2_sys::SDL_PixelFormatEnum::SDL_PIXELFORMAT_RGBA32 as u32,
+            sdl2_sys::SDL_TextureAccess::SDL_TEXTUREACCESS_STREAMING as i32,
+            width, height
+        )
+    };
+
+    machine.push(Value::Ptr(texture as *mut u32));
+    Ok(())
+}
+
+fn destroy_texture(interpreter: *mut Interpreter) -> Result<(), GiffiError> {
+    let machine = unsafe { interpreter.as_mut().unwrap() };
+
+    let texture = machine.pop_ptr()? as *mut sdl2_sys::SDL_Texture;
+
+    // SDL2 Calls
+    unsafe {
+        sdl2_sys::SDL_DestroyTexture(texture);
+    }
+    Ok(())
+}
+
+/**
+ * Replaces all the pixels of a texture, the buffer has to hold exactly width * height * 4 bytes.
+ */
+fn update_texture(interpreter: *mut Interpreter) -> Result<(), GiffiError> {
+    let machine = unsafe { interpreter.as_mut().unwrap() };
+
+    let pixels = machine.pop_bytes()?;
+    let texture = machine.pop_ptr()? as *mut sdl2_sys::SDL_Texture;
+
+    // SDL2 Calls
+    let (mut width, mut height) = (0, 0);
+    let queried = unsafe {
+        sdl2_sys::SDL_QueryTexture(texture, std::ptr::null_mut(), std::ptr::null_mut(), &mut width, &mut height)
+    };
+    if queried != 0 {
+        return Err(machine.error("Cannot update an invalid texture".to_string()));
+    }
+
+    let pixels = pixels.borrow();
+    let expected = width as usize * height as usize * 4;
+    if pixels.len() != expected {
+        return Err(machine.error_of_kind(ErrorKind::IndexOutOfBounds,
+            format!("A {}x{} texture needs {} bytes of pixels, got {}", width, height, expected, pixels.len())));
+    }
+    unsafe {
+        sdl2_sys::SDL_UpdateTexture(texture, std::ptr::null(), pixels.as_ptr() as *const _, width * 4);
+    }
+    Ok(())
+}
+
+fn render_copy(interpreter: *mut Interpreter) -> Result<(), GiffiError> {
+    let machine = unsafe { interpreter.as_mut().unwrap() };
+
+    let texture = machine.pop_ptr()? as *mut sdl2_sys::SDL_Texture;
+    let renderer = machine.pop_ptr()? as *mut sdl2_sys::SDL_Renderer;
+
+    // SDL2 Calls, the texture is stretched over the whole window
+    unsafe {
+        sdl2_sys::SDL_RenderCopy(renderer, texture, std::ptr::null(), std::ptr::null());
+    }
+    Ok(())
+}
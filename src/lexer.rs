@@ -1,9 +1,15 @@
+use crate::ast::Span;
+use crate::error::{GiffiError, ErrorKind};
 use crate::value::Value;
 use std::collections::{VecDeque};
+use std::iter::Peekable;
+use std::str::Chars;
 
-const SYMBOLS: [char; 7] = ['{', '}', ',', ':', ';', '[', ']'];
-const OPERATORS: [&'static str; 16] = ["+", "-", "/", "*", "%", "<", ">", "(", ")", "=", "!", "|", "==", "!=", "||" , "&&"];
-const KEYWORDS: [&'static str; 9] = ["let", "return", "fn", "if", "else", "while", "import", "break", "continue"];
+const SYMBOLS: [char; 8] = ['{', '}', ',', ':', ';', '[', ']', '.'];
+const OPERATORS: [&str; 39] = ["+", "-", "/", "*", "**", "%", "<", ">", "(", ")", "=", "!", "~", "|", "&", "^", "==", "!=", "<=", ">=", "<<", ">>", "||" , "&&",
+    "+=", "-=", "*=", "/=", "%=", "&=", "|=", "^=", "<<=", ">>=", "**=", "..", "..=", "...", "=>"];
+const KEYWORDS: [&str; 18] = ["let", "return", "fn", "if", "else", "match", "while", "for", "in", "import", "break", "continue", "struct", "impl",
+    "try", "catch", "finally", "throw"];
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum LexerTokenType {
@@ -12,14 +18,21 @@ pub enum LexerTokenType {
     Symbol(char),
     Operator(String),
     Identifier(String),
+    Template(Vec<TemplatePart>), // String with embedded expressions "Score: ${score}"
     NewLine,
     Eof
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct LexerToken {
     pub token: LexerTokenType,
-    pub line: u16, pub column: u16
+    pub line: u32, pub column: u32
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TemplatePart {
+    Text(String),
+    Code(VecDeque<LexerToken>), // Tokens of the expression inside "${...}", ending with an Eof
 }
 
 #[derive(PartialEq)]
@@ -31,33 +44,34 @@ enum CommentType {
 
 #[derive(Debug)]
 pub struct Lexer {
-    is_literal: bool,
     current_word: String,
     lexer_tokens: VecDeque<LexerToken>,
-    line: u16, column: u16,
+    line: u32, column: u32,             // Position of the last read character
+    token_line: u32, token_column: u32, // Where the token being read started
 }
 
 impl Lexer {
-    pub fn lex(code: String) -> VecDeque<LexerToken> {
+    pub fn lex(code: String) -> Result<VecDeque<LexerToken>, GiffiError> {
         let mut lexer = Lexer::new();
-
-        let mut is_commented = CommentType::None;
         let mut iter = code.chars().peekable();
 
-        loop {
-            let opt_c = iter.next();
-            if opt_c.is_none() {
-                break;
-            }
-            let c = opt_c.unwrap();
+        lexer.lex_tokens(&mut iter, false)?;
 
-            // Counter
-            lexer.column += 1;
-            if c == '\n' {
-                lexer.line += 1;
-                lexer.column = 1;
-            }
+        lexer.mark_token();
+        lexer.push_token(LexerTokenType::Eof);
+        Ok(lexer.lexer_tokens)
+    }
+
+    /**
+     * Nested lexing stops at the '}' closing a "${...}" in a string, it's read but not pushed.
+     */
+    fn lex_tokens(&mut self, iter: &mut Peekable<Chars>, nested: bool) -> Result<(), GiffiError> {
+        let lexer = self;
+        let start = Span::new(lexer.line, lexer.column);
+        let mut brace_depth = 0;
+        let mut is_commented = CommentType::None;
 
+        while let Some(c) = lexer.next_char(iter) {
             // Comments
             if is_commented == CommentType::Line {
                 if c == '\n' {
@@ -102,31 +116,71 @@ impl Lexer {
                 }
             }
             
-            // Strings
+            // Strings, raw strings start with an "r" r"C:\path"
             if c == '"' {
-                lexer.flush();
-                lexer.is_literal = !lexer.is_literal;
+                let raw = lexer.current_word == "r";
+                if raw {
+                    lexer.current_word.clear();
+                }
+                else {
+                    lexer.flush();
+                    lexer.mark_token();
+                }
+                lexer.lex_string(iter, raw)?;
                 continue;
             }
-            if lexer.is_literal {
-                lexer.current_word.push(c);
+
+            if c == '\'' {
+                lexer.flush();
+                lexer.mark_token();
+                lexer.lex_char(iter)?;
                 continue;
             }
-            
+
+            if nested && c == '}' {
+                if brace_depth == 0 {
+                    lexer.flush();
+                    return Ok(());
+                }
+                brace_depth -= 1;
+            }
+            if nested && c == '{' {
+                brace_depth += 1;
+            }
+
             if c.is_whitespace() {
                 lexer.flush();
                 continue;
             }
+            if c.is_ascii_digit() && lexer.current_word.is_empty() {
+                lexer.mark_token();
+                lexer.lex_number(c, iter)?;
+                continue;
+            }
+            // Ranges "1..5", "1..=5" and the rest of an array pattern "...rest"
+            if c == '.' && iter.peek() == Some(&'.') {
+                lexer.flush();
+                lexer.mark_token();
+                lexer.next_char(iter);
+                lexer.current_word.push_str("..");
+                if let Some(next_c @ ('=' | '.')) = iter.peek() {
+                    lexer.current_word.push(*next_c);
+                    lexer.next_char(iter);
+                }
+                lexer.flush();
+                continue;
+            }
             if SYMBOLS.contains(&c)
             {
                 lexer.flush();
+                lexer.mark_token();
                 lexer.push_token(LexerTokenType::Symbol(c));
                 continue;
             }
             if OPERATORS.contains(&String::from(c).as_str())
             {
                 lexer.flush();
-                
+                lexer.mark_token();
                 lexer.current_word.push(c);
                 let peek = iter.peek();
                 if peek.is_none() {
@@ -134,17 +188,20 @@ impl Lexer {
                     break;
                 }
                 else {
-                    // Negative numbers
                     let peeked_c = peek.unwrap();
-                    if c == '-' && peeked_c.is_numeric() {
-                        continue;
-                    }
 
-                    // 2 char operators like "==", "&&"
+                    // 2 char operators like "==", "&&" and 3 char ones like "<<="
                     let possible_op = format!("{}{}", c, peeked_c);
                     if OPERATORS.contains(&possible_op.as_str()) {
-                        iter.next();
+                        lexer.next_char(iter);
                         lexer.current_word = possible_op;
+                        if let Some(third_c) = iter.peek() {
+                            let possible_op = format!("{}{}", lexer.current_word, third_c);
+                            if OPERATORS.contains(&possible_op.as_str()) {
+                                lexer.next_char(iter);
+                                lexer.current_word = possible_op;
+                            }
+                        }
                         lexer.flush();
                     }
                     else {
@@ -155,34 +212,275 @@ impl Lexer {
                 }
             }
 
+            if lexer.current_word.is_empty() {
+                lexer.mark_token();
+            }
             lexer.current_word.push(c);
         }
         lexer.flush();
 
-        if lexer.is_literal {
-            panic!("String literal is missing a '\"'");
+        if nested {
+            return Err(Lexer::error("'${' in a string is missing a '}'".to_string(), start));
         }
-        
-        lexer.push_token(LexerTokenType::Eof);
-        lexer.lexer_tokens
+        Ok(())
     }
 
     fn new() -> Lexer {
         Lexer{
-            is_literal: false,
             current_word: String::from(""),
             lexer_tokens: VecDeque::new(),
-            line: 1, column: 1
+            line: 1, column: 0,
+            token_line: 1, token_column: 0,
         }
     }
 
-    fn flush(&mut self) {
-        // Literals can be empty (just 2 quotes)!
-        if self.is_literal {
-            self.push_token(LexerTokenType::Value(Value::Literal(self.current_word.clone())));
-            self.current_word.clear();
-            return;
+    /**
+     * Every character has to be read through here to keep the position right.
+     */
+    fn next_char(&mut self, iter: &mut Peekable<Chars>) -> Option<char> {
+        let c = iter.next()?;
+        self.column += 1;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 0;
+        }
+        Some(c)
+    }
+
+    /**
+     * The next token starts at the last read character.
+     */
+    fn mark_token(&mut self) {
+        self.token_line = self.line;
+        self.token_column = self.column;
+    }
+
+    /**
+     * The opening quote is already read. Triple quoted strings """...""" can contain quotes.
+     * Escapes like "\n" and "\u{1F600}" are replaced and "${<expr>}" is lexed into a Template,
+     * except in raw strings.
+     */
+    fn lex_string(&mut self, iter: &mut Peekable<Chars>, raw: bool) -> Result<(), GiffiError> {
+        let start = Span::new(self.token_line, self.token_column);
+        let triple = Lexer::starts_with(iter, "\"\"");
+        if triple {
+            self.next_char(iter);
+            self.next_char(iter);
+        }
+
+        let mut parts = vec![];
+        let mut string = String::new();
+        loop {
+            let Some(c) = self.next_char(iter) else {
+                return Err(Lexer::error("String literal is missing a '\"'".to_string(), start));
+            };
+            if c == '"' {
+                if !triple {
+                    break;
+                }
+                if Lexer::starts_with(iter, "\"\"") {
+                    self.next_char(iter);
+                    self.next_char(iter);
+                    break;
+                }
+            }
+            if c == '\\' && !raw {
+                string.push(self.lex_escape(iter)?);
+                continue;
+            }
+            if c == '$' && !raw && iter.peek() == Some(&'{') {
+                self.next_char(iter);
+                if !string.is_empty() {
+                    parts.push(TemplatePart::Text(std::mem::take(&mut string)));
+                }
+                parts.push(TemplatePart::Code(self.lex_interpolation(iter)?));
+                continue;
+            }
+            string.push(c);
+        }
+
+        // The string's tokens are pushed with its start
+        self.token_line = start.line;
+        self.token_column = start.column;
+        if parts.is_empty() {
+            self.push_token(LexerTokenType::Value(Value::Literal(string)));
+            return Ok(());
+        }
+        if !string.is_empty() {
+            parts.push(TemplatePart::Text(string));
+        }
+        self.push_token(LexerTokenType::Template(parts));
+        Ok(())
+    }
+
+    /**
+     * The "${" is already read, lexes the expression up to the matching '}'.
+     */
+    fn lex_interpolation(&mut self, iter: &mut Peekable<Chars>) -> Result<VecDeque<LexerToken>, GiffiError> {
+        let outer_tokens = std::mem::take(&mut self.lexer_tokens);
+        let result = self.lex_tokens(iter, true);
+        let mut tokens = std::mem::replace(&mut self.lexer_tokens, outer_tokens);
+        result?;
+
+        self.mark_token();
+        tokens.push_back(LexerToken { token: LexerTokenType::Eof, line: self.token_line, column: self.token_column });
+        Ok(tokens)
+    }
+
+    /**
+     * The opening quote is already read. A char is exactly one character or escape 'a', '\n', '\u{1F600}'.
+     */
+    fn lex_char(&mut self, iter: &mut Peekable<Chars>) -> Result<(), GiffiError> {
+        let start = Span::new(self.token_line, self.token_column);
+        let error = |msg: &str| Lexer::error(msg.to_string(), start);
+        let c = match self.next_char(iter) {
+            Some('\\') => self.lex_escape(iter)?,
+            Some('\'') => { return Err(error("Empty char literal ''")); }
+            Some(c) => c,
+            None => { return Err(error("Char literal is missing its closing quote")); }
+        };
+        if self.next_char(iter) != Some('\'') {
+            return Err(error("Char literal is missing its closing quote, strings use double quotes \"...\""));
         }
+        self.push_token(LexerTokenType::Value(Value::Char(c)));
+        Ok(())
+    }
+
+    /**
+     * The backslash is already read, errors point at it.
+     */
+    fn lex_escape(&mut self, iter: &mut Peekable<Chars>) -> Result<char, GiffiError> {
+        let start = Span::new(self.line, self.column);
+        let error = |msg: String| Lexer::error(msg, start);
+        match self.next_char(iter) {
+            Some('n') => { return Ok('\n'); }
+            Some('t') => { return Ok('\t'); }
+            Some('r') => { return Ok('\r'); }
+            Some('0') => { return Ok('\0'); }
+            Some('\\') => { return Ok('\\'); }
+            Some('"') => { return Ok('"'); }
+            Some('\'') => { return Ok('\''); }
+            Some('$') => { return Ok('$'); }
+            Some('u') => {
+                if self.next_char(iter) != Some('{') {
+                    return Err(error("Expected '{' after '\\u'".to_string()));
+                }
+                let mut hex = String::new();
+                loop {
+                    match self.next_char(iter) {
+                        Some('}') => { break; }
+                        Some(c) if c.is_ascii_hexdigit() && hex.len() < 6 => { hex.push(c); }
+                        _ => { return Err(error("Expected up to 6 hex digits and a '}' in '\\u{...}'".to_string())); }
+                    }
+                }
+                let code = u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32);
+                return code.ok_or(error(format!("'\\u{{{}}}' is not a valid unicode character", hex)));
+            }
+            Some(c) => { return Err(error(format!("Unknown escape sequence '\\{}'", c))); }
+            None => { return Err(error("String literal is missing a '\"'".to_string())); }
+        }
+    }
+
+    /**
+     * The first digit is already read. Hex "0xFF", binary "0b1010" and octal "0o755" are Ints,
+     * decimal numbers with a fraction "1.5", an exponent "1e-3" or a suffix "1f", "1f64" are Floats.
+     * Digits can be separated with '_' "1_000_000". Errors point at the start of the number.
+     */
+    fn lex_number(&mut self, first: char, iter: &mut Peekable<Chars>) -> Result<(), GiffiError> {
+        let start = Span::new(self.token_line, self.token_column);
+        let error = |msg: String| Lexer::error(msg, start);
+
+        let radix = match (first, iter.peek()) {
+            ('0', Some('x' | 'X')) => 16,
+            ('0', Some('b' | 'B')) => 2,
+            ('0', Some('o' | 'O')) => 8,
+            _ => 10,
+        };
+        let mut literal = String::from(first);
+        let mut digits = String::new();
+        let mut is_float = false;
+        if radix == 10 {
+            digits.push(first);
+            self.lex_digits(iter, 10, &mut literal, &mut digits);
+
+            let mut ahead = iter.clone();
+            if ahead.next() == Some('.') && ahead.next().is_some_and(|c| c.is_ascii_digit()) {
+                is_float = true;
+                literal.push(self.next_char(iter).unwrap());
+                digits.push('.');
+                self.lex_digits(iter, 10, &mut literal, &mut digits);
+            }
+            if let Some(exponent @ ('e' | 'E')) = iter.peek().copied() {
+                is_float = true;
+                self.next_char(iter);
+                literal.push(exponent);
+                digits.push('e');
+                if let Some(sign @ ('+' | '-')) = iter.peek().copied() {
+                    self.next_char(iter);
+                    literal.push(sign);
+                    digits.push(sign);
+                }
+                if !self.lex_digits(iter, 10, &mut literal, &mut digits) {
+                    return Err(error(format!("Expected digits after the exponent in '{}'", literal)));
+                }
+            }
+        }
+        else {
+            literal.push(self.next_char(iter).unwrap());
+            if !self.lex_digits(iter, radix, &mut literal, &mut digits) {
+                return Err(error(format!("Expected digits after '{}'", literal)));
+            }
+        }
+
+        // Letters right after the digits "12px", "0b102" are a suffix
+        let mut suffix = String::new();
+        while let Some(c) = iter.peek().copied().filter(|c| c.is_alphanumeric() || *c == '_') {
+            self.next_char(iter);
+            suffix.push(c);
+        }
+        match suffix.as_str() {
+            "" => {}
+            "f" | "f64" if radix == 10 => { is_float = true; }
+            _ => { return Err(error(format!("Invalid suffix '{}' on the number '{}'", suffix, literal))); }
+        }
+
+        if is_float {
+            let value = digits.parse::<f64>().ok().filter(|f| f.is_finite());
+            let Some(value) = value else {
+                return Err(error(format!("Float literal '{}' is out of range", literal)));
+            };
+            self.push_token(LexerTokenType::Value(Value::Float(value)));
+            return Ok(());
+        }
+        let Ok(value) = i64::from_str_radix(&digits, radix) else {
+            return Err(error(format!("Integer literal '{}' doesn't fit into 64 bits", literal)));
+        };
+        self.push_token(LexerTokenType::Value(Value::Int(value)));
+        Ok(())
+    }
+
+    /**
+     * Returns whether there was at least one digit, '_' separators only go into the literal.
+     */
+    fn lex_digits(&mut self, iter: &mut Peekable<Chars>, radix: u32, literal: &mut String, digits: &mut String) -> bool {
+        let mut found = false;
+        while let Some(c) = iter.peek().copied().filter(|c| c.is_digit(radix) || *c == '_') {
+            self.next_char(iter);
+            literal.push(c);
+            if c != '_' {
+                digits.push(c);
+                found = true;
+            }
+        }
+        found
+    }
+
+    fn starts_with(iter: &Peekable<Chars>, prefix: &str) -> bool {
+        let mut ahead = iter.clone();
+        prefix.chars().all(|c| ahead.next() == Some(c))
+    }
+
+    fn flush(&mut self) {
         if self.current_word.is_empty() {
             return;
         }
@@ -191,7 +489,8 @@ impl Lexer {
         self.current_word.clear();
 
         
-        if let Ok(v) = Value::parse(&word) {
+        // Numbers are lexed on their own, "inf" and "nan" stay identifiers
+        if let ("true" | "false" | "null", Ok(v)) = (word.as_str(), Value::parse(&word)) {
             self.push_token(LexerTokenType::Value(v));
         }
         else if OPERATORS.contains(&word.as_str()) { 
@@ -205,10 +504,138 @@ impl Lexer {
         }
     }
 
+    /**
+     * Errors point at where the token or escape they're about starts, not at where the lexer noticed.
+     */
+    fn error(msg: String, span: Span) -> GiffiError {
+        GiffiError::new(ErrorKind::Lex, msg, span)
+    }
+
     fn push_token(&mut self, tk: LexerTokenType) {
         self.lexer_tokens.push_back(
-            LexerToken { token: tk, line: self.line, column: self.column }
+            LexerToken { token: tk, line: self.token_line, column: self.token_column }
         );
     }
 
 }
+
+#[cfg(test)]
+mod test {
+    use crate::error::{ErrorKind};
+    use crate::value::Value;
+    use super::{Lexer, LexerTokenType, TemplatePart};
+
+    fn lex_string(code: &str) -> String {
+        let tokens = Lexer::lex(code.to_string()).unwrap();
+        if let LexerTokenType::Value(Value::Literal(string)) = &tokens[0].token {
+            return string.clone();
+        }
+        panic!("expected a string, got {:?}", tokens[0].token);
+    }
+
+    #[test]
+    fn test_string_literals() {
+        assert_eq!(lex_string(r#""a\tb\nc""#), "a\tb\nc");
+        assert_eq!(lex_string(r#""say \"hi\" \\ \u{1F600}""#), "say \"hi\" \\ \u{1F600}");
+        assert_eq!(lex_string(r#"r"C:\new\table""#), "C:\\new\\table");
+        assert_eq!(lex_string("\"\"\"first \"line\"\nsecond\\n\"\"\""), "first \"line\"\nsecond\n");
+        assert_eq!(lex_string(r#""http://example.com /* not a comment */""#), "http://example.com /* not a comment */");
+        assert_eq!(lex_string(r#""""#), "");
+    }
+
+    #[test]
+    fn test_positions() {
+        let tokens = Lexer::lex("let x = \"a\";\n  y".to_string()).unwrap();
+        let positions: Vec<(u32, u32)> = tokens.iter().map(|tk| (tk.line, tk.column)).collect();
+        assert_eq!(positions[..6], [(1, 1), (1, 5), (1, 7), (1, 9), (1, 12), (2, 3)]);
+
+        let err = Lexer::lex(r#"let s = "ab\q";"#.to_string()).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Lex);
+        assert_eq!((err.span.line, err.span.column), (1, 12));
+
+        let err = Lexer::lex("let a = 1;\nlet s = \"\\u{zz}\";".to_string()).unwrap_err();
+        assert_eq!((err.span.line, err.span.column), (2, 10));
+
+        let err = Lexer::lex("let a = 1;\nlet s = \"\\u{110000}\";".to_string()).unwrap_err();
+        assert_eq!((err.span.line, err.span.column), (2, 10));
+
+        let err = Lexer::lex("let a = 1;\n\nlet s = \"open".to_string()).unwrap_err();
+        assert_eq!((err.span.line, err.span.column), (3, 9));
+
+        // Positions past 65535 still fit
+        let long = format!("let s = \"{}\"; x", "a".repeat(70000));
+        let tokens = Lexer::lex(long).unwrap();
+        assert_eq!((tokens[5].line, tokens[5].column), (1, 70013));
+        let tall = format!("{}x", "\n".repeat(70000));
+        let tokens = Lexer::lex(tall).unwrap();
+        assert_eq!((tokens[0].line, tokens[0].column), (70001, 1));
+    }
+
+    #[test]
+    fn test_chars() {
+        let char = |code: &str| Lexer::lex(code.to_string()).unwrap()[0].token.clone();
+        assert_eq!(char("'a'"), LexerTokenType::Value(Value::Char('a')));
+        assert_eq!(char("'\"'"), LexerTokenType::Value(Value::Char('"')));
+        assert_eq!(char("'\\''"), LexerTokenType::Value(Value::Char('\'')));
+        assert_eq!(char("'\\u{e9}'"), LexerTokenType::Value(Value::Char('é')));
+
+        for code in ["x = '';", "x = 'ab';", "x = 'a"] {
+            let err = Lexer::lex(code.to_string()).unwrap_err();
+            assert_eq!(err.kind, ErrorKind::Lex, "{}", code);
+            assert_eq!((err.span.line, err.span.column), (1, 5), "{}", code);
+        }
+    }
+
+    #[test]
+    fn test_numbers() {
+        let number = |code: &str| Lexer::lex(code.to_string()).unwrap()[0].token.clone();
+        assert_eq!(number("0xFF00ff"), LexerTokenType::Value(Value::Int(0xFF00FF)));
+        assert_eq!(number("0b1010"), LexerTokenType::Value(Value::Int(10)));
+        assert_eq!(number("0o755"), LexerTokenType::Value(Value::Int(0o755)));
+        assert_eq!(number("1_000_000"), LexerTokenType::Value(Value::Int(1_000_000)));
+        assert_eq!(number("0x7FFF_FFFF_FFFF_FFFF"), LexerTokenType::Value(Value::Int(i64::MAX)));
+        assert_eq!(number("1.5e3"), LexerTokenType::Value(Value::Float(1500.0)));
+        assert_eq!(number("2E-2"), LexerTokenType::Value(Value::Float(0.02)));
+        assert_eq!(number("1e+2"), LexerTokenType::Value(Value::Float(100.0)));
+        assert_eq!(number("3f"), LexerTokenType::Value(Value::Float(3.0)));
+        assert_eq!(number("1_0.2_5f64"), LexerTokenType::Value(Value::Float(10.25)));
+        assert_eq!(number("inf"), LexerTokenType::Identifier("inf".to_string()));
+
+        // A range or a method call after an integer
+        let tokens = Lexer::lex("1..2".to_string()).unwrap();
+        assert_eq!(tokens[1].token, LexerTokenType::Operator("..".to_string()));
+        let tokens = Lexer::lex("1.abs()".to_string()).unwrap();
+        assert_eq!(tokens[1].token, LexerTokenType::Symbol('.'));
+
+        for (code, column) in [("x = 9223372036854775808;", 5), ("x = 0x1_0000_0000_0000_0000;", 5), ("x = 1e400;", 5),
+            ("x = 0b102;", 5), ("x = 0x;", 5), ("x = 12px;", 5), ("x = 1e;", 5), ("x = 0o8;", 5), ("x = 0xFFg;", 5)] {
+            let err = Lexer::lex(code.to_string()).unwrap_err();
+            assert_eq!(err.kind, ErrorKind::Lex, "{}", code);
+            assert_eq!((err.span.line, err.span.column), (1, column), "{}", code);
+        }
+    }
+
+    #[test]
+    fn test_template() {
+        let tokens = Lexer::lex("x = \"a${b + 1}\";".to_string()).unwrap();
+        assert_eq!((tokens[2].line, tokens[2].column), (1, 5));
+        let LexerTokenType::Template(parts) = &tokens[2].token else {
+            panic!("expected a template, got {:?}", tokens[2].token);
+        };
+        assert_eq!(parts[0], TemplatePart::Text("a".to_string()));
+        let TemplatePart::Code(code) = &parts[1] else {
+            panic!("expected code, got {:?}", parts[1]);
+        };
+        let code: Vec<(LexerTokenType, u32)> = code.iter().map(|tk| (tk.token.clone(), tk.column)).collect();
+        assert_eq!(code, [
+            (LexerTokenType::Identifier("b".to_string()), 9),
+            (LexerTokenType::Operator("+".to_string()), 11),
+            (LexerTokenType::Value(Value::Int(1)), 13),
+            (LexerTokenType::Eof, 14),
+        ]);
+        assert_eq!(tokens[3].token, LexerTokenType::Symbol(';'));
+
+        let err = Lexer::lex("let s = 1;\nlet t = \"${s + 1\";".to_string()).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Lex);
+    }
+}
@@ -2,13 +2,26 @@
 #![allow(unused_imports)]
 #![allow(unreachable_patterns)]
 #![allow(unreachable_code)]
+// The code base prefers explicit returns and field names over clippy's shorthand style
+#![allow(clippy::needless_return)]
+#![allow(clippy::redundant_field_names)]
+#![allow(clippy::clone_on_copy)]
+#![allow(clippy::useless_format)]
+#![allow(clippy::len_zero)]
+#![allow(clippy::ptr_arg)]
+#![allow(clippy::unnecessary_unwrap)]
+#![allow(clippy::new_without_default)]
 
 use std::{env, io::{self, Write}};
+use colored::Colorize;
 
-mod expr;
+mod ast;
+mod error;
 mod value;
 mod lexer;
 mod parser;
+mod bytecode;
+mod compiler;
 mod interpreter;
 mod giffiscript;
 
@@ -27,13 +40,16 @@ fn main() {
     let mut machine = giffiscript::GiffiScript::new();
     
     // run file
-    let mut args: Vec<String> = env::args().collect();
+    let args: Vec<String> = env::args().collect();
     if args.len() > 1 {
         let code = std::fs::read_to_string(&args[1]);
         if code.is_err() {
             panic!("Error occured when trying to read file: {}", code.unwrap_err());
         }
-        machine.execute(code.unwrap());
+        if let Err(e) = machine.execute(code.unwrap()) {
+            println!("{}", e.to_string().red());
+            std::process::exit(-1);
+        }
         return;
     }
     
@@ -42,6 +58,8 @@ fn main() {
     loop {
         let st = get_line();
         let code = st.replace("\n", "");
-        machine.execute(code);
+        if let Err(e) = machine.execute(code) {
+            println!("{}", e.to_string().red());
+        }
     }
 }
@@ -1,54 +1,28 @@
 use core::panic;
 use std::collections::{VecDeque};
 
-use crate::interpreter::{Interpreter};
-use crate::lexer::{LexerTokenType, LexerToken, Lexer};
+use crate::ast::{Stmt, StmtKind, Expr, ExprKind, MatchArm, Pattern, Span};
+use crate::error::{GiffiError, ErrorKind};
+use crate::lexer::{LexerTokenType, LexerToken, Lexer, TemplatePart};
 use crate::value::Value;
-use crate::expr::{AstExpr};
+
+const UNARY_PRECEDENCE:u8 = 10; // Prefix operators sit between the multiplication and the power operator
+const MAX_PRECEDENCE:u8 = 12;
 
 pub struct Parser {
     input: VecDeque<LexerToken>,
-    is_expr: bool,
-    last_line: u16, last_column: u16
-}
-
-#[derive(Debug, Clone, PartialEq)]
-pub enum ParserToken {
-    Nop, // fallback used by errors.
-    DeclareVariable(String), // Pops a value from stack and stores it to stack
-    StoreVariable(String),   // Pops and stores it
-    MakeArray(u32),          // How many arguments to pop from the stack to create the array
-    GetArrayElement(Vec<ParserToken>),
-    GetVariableArrayElement(String, Vec<ParserToken>),
-    StoreVariableArrayElement(String), // first pop is assignment, second is index.
-    DeclareFunction(String, Vec<ParserToken>),
-    GetVariable(String),     // Pushes the variables value to stack
-    Operation(String),       // Pops 2 values from stack as arguments and pushes a result
-    Push(Value),
-    Pop(),
-    Continue(),
-    Break(),
-    If(Vec<ParserToken>, Vec<ParserToken>), // Pops value, if true executes first, else the second
-    While(Vec<ParserToken>, Vec<ParserToken>), // First expression used for comparision, if true executes second (which is the body)
-    Call(String, Vec<ParserToken>), // Second are arguments, executed before calling.
-    CallNative(fn(*mut Interpreter)), // Added by libraries when imported
-    Return(),
-    Import(String),
+    last_line: u32, last_column: u32,
+    no_struct_literal: bool, // Set in conditions, "if x { ... }" is not a struct literal
 }
 
 impl Parser {
-    #[must_use]
-    pub fn parse(tokens: VecDeque<LexerToken>, is_expr: bool) -> Vec<ParserToken> {
-        let mut parser = Parser::new(tokens, is_expr);
+    pub fn parse(tokens: VecDeque<LexerToken>) -> Result<Vec<Stmt>, GiffiError> {
+        let mut parser = Parser::new(tokens);
         parser.parse_until(LexerTokenType::Eof)
     }
 
-    #[must_use]
-    fn parse_until(&mut self, tk: LexerTokenType) -> Vec<ParserToken> {
-        assert!(!self.is_expr);
-
-        let mut tokens = vec![];
-        let mut scopes: Vec<char> = vec![];
+    fn parse_until(&mut self, tk: LexerTokenType) -> Result<Vec<Stmt>, GiffiError> {
+        let mut statements = vec![];
         'parse_loop : loop {
             let peek = self.peek();
             if peek.is_none() {
@@ -57,506 +31,970 @@ impl Parser {
 
             // Terminator
             let token = peek.unwrap();
-            if scopes.len() == 0 && &token.token == &tk {
+            if token.token == tk {
                 break 'parse_loop;
             }
-            // Scopes
-            if let LexerTokenType::Symbol(symbol) = &token.token {
-                if symbol == &'{' {
-                    scopes.push('{');
-                }
-                else if symbol == &'}' {
-                    let symbol = scopes.pop().expect(format!("not matching '}}' for '{{' got {} instead", symbol).as_str());
-                    if symbol != '{' {
-                        self.error(format!("not matching '}}' for '{{' got {} instead", symbol));
-                    }
-                }
+            if token.token == LexerTokenType::Eof {
+                return Err(self.error(format!("Expected {:?} got EOF instead!", tk)));
             }
-            else if let LexerTokenType::Keyword(kw) = &token.token {
-                match kw.as_str() {
-                    "let" => { 
-                        tokens.append(&mut self.variable_decleration());
-                    },
-                    "fn" => { 
-                        tokens.append(&mut self.function_decleration());
-                    },
-                    "return" => { 
-                        tokens.append(&mut self.function_return());
-                    },
-                    "if" => {
-                        tokens.append(&mut self.if_statement())
-                    }
-                    "while" => {
-                        tokens.append(&mut self.while_statement())
-                    }
-                    "continue" => {
-                        self.eat().unwrap();
-                        tokens.push(ParserToken::Continue());
-                        self.eat_expect(LexerTokenType::Symbol(';'));
-                    }
-                    "break" => {
-                        self.eat().unwrap();
-                        tokens.push(ParserToken::Break());
-                        self.eat_expect(LexerTokenType::Symbol(';'));
-                    }
-                    "import" => {
-                        tokens.append(&mut self.import_keyword())
+            if token.token == LexerTokenType::NewLine {
+                self.eat();
+                continue;
+            }
+
+            let statement = self.parse_statement()?;
+            statements.push(statement);
+        }
+        Ok(statements)
+    }
+
+    fn parse_statement(&mut self) -> Result<Stmt, GiffiError> {
+        let token = self.peek().expect("parse_statement called without tokens").clone();
+        let span = Span::new(token.line, token.column);
+
+        if let LexerTokenType::Keyword(kw) = &token.token {
+            match kw.as_str() {
+                "let" => {
+                    return self.variable_decleration();
+                },
+                "fn" => {
+                    // "fn(a) { ... }" without a name is a function expression
+                    if let Some(LexerToken { token: LexerTokenType::Identifier(_), .. }) = self.input.get(1) {
+                        return self.function_decleration();
                     }
-                    _ => { self.error(format!("Unimplumented keyword {}", kw)); }
+                },
+                "return" => {
+                    return self.function_return();
+                },
+                "if" => {
+                    return self.if_statement();
+                }
+                "match" => {
+                    return self.match_statement();
+                }
+                "try" => {
+                    return self.try_statement();
+                }
+                "throw" => {
+                    self.eat().unwrap();
+                    let expr = self.parse_expression()?;
+                    self.eat_expect(LexerTokenType::Symbol(';'))?;
+                    return Ok(Stmt::new(StmtKind::Throw(expr), span));
+                }
+                "while" => {
+                    return self.while_statement();
+                }
+                "for" => {
+                    return self.for_statement();
+                }
+                "continue" => {
+                    self.eat().unwrap();
+                    self.eat_expect(LexerTokenType::Symbol(';'))?;
+                    return Ok(Stmt::new(StmtKind::Continue, span));
+                }
+                "break" => {
+                    self.eat().unwrap();
+                    self.eat_expect(LexerTokenType::Symbol(';'))?;
+                    return Ok(Stmt::new(StmtKind::Break, span));
+                }
+                "import" => {
+                    return self.import_keyword();
+                }
+                "struct" => {
+                    return self.struct_decleration();
+                }
+                "impl" => {
+                    return self.impl_block();
                 }
+                _ => { return Err(self.error(format!("Unimplumented keyword {}", kw))); }
+            }
+        }
+
+        // Assignments "foo = 2;", "foo[0] += 2;" and calls "foo();"
+        let statement = self.simple_statement(span)?;
+        self.eat_expect(LexerTokenType::Symbol(';'))?;
+        Ok(statement)
+    }
 
+    /**
+     * Assignment "foo[0] += 2" or an expression "foo()", without the ';'
+     */
+    fn simple_statement(&mut self, span: Span) -> Result<Stmt, GiffiError> {
+        let expr = self.parse_expression()?;
+        if let Some(LexerToken { token: LexerTokenType::Operator(op), .. }) = self.peek() {
+            if op == "=" || Parser::compound_operator(op).is_some() {
+                return self.variable_assignment(expr);
             }
-            else if let LexerTokenType::Identifier(ident) = token.token.clone() {
-                self.eat(); // Identifier
-                let next = self.eat();
-                let tk = next.unwrap();
-                
-                // Is array
-                if let LexerTokenType::Symbol(symbol) = &tk.token {
-                    if symbol == &'[' {
-                        tokens.append(&mut self.array_assignment(ident.clone()));
-                    }
+        }
+        Ok(Stmt::new(StmtKind::Expr(expr), span))
+    }
+
+    /**
+     * Parses an expression like "foo + 5 * bar(2)" into a tree, stops at the first token which cannot
+     * continue the expression (e.g. ';', ',', ')' or '{'), the terminator doesn't get eaten.
+     */
+    fn parse_expression(&mut self) -> Result<Expr, GiffiError> {
+        let mut start = None;
+        if !self.peek_is_range() {
+            let expr = self.parse_binary(0)?;
+            if !self.peek_is_range() {
+                return Ok(expr);
+            }
+            start = Some(Box::new(expr));
+        }
+        self.range(start)
+    }
+
+    fn peek_is_range(&self) -> bool {
+        return self.peek_is(LexerTokenType::Operator("..".to_string())) || self.peek_is(LexerTokenType::Operator("..=".to_string()));
+    }
+
+    /**
+     * Ranges bind looser than any binary operator, "a + 1..b * 2" is "(a + 1)..(b * 2)".
+     * The start is already parsed, "..<end>" is left. The end is left out when nothing follows which can start an expression.
+     */
+    fn range(&mut self, start: Option<Box<Expr>>) -> Result<Expr, GiffiError> {
+        let token = self.eat_checked()?;
+        let span = start.as_ref().map(|start| start.span).unwrap_or(Span::new(token.line, token.column));
+        let inclusive = token.token == LexerTokenType::Operator("..=".to_string());
+
+        let has_end = match self.peek().map(|tk| &tk.token) {
+            None | Some(LexerTokenType::Eof) => false,
+            Some(LexerTokenType::Symbol(c)) => !(*c == ']' || *c == ';' || *c == ',' || *c == '}' || (*c == '{' && self.no_struct_literal)),
+            Some(LexerTokenType::Operator(op)) => op != ")",
+            _ => true,
+        };
+        if !has_end {
+            if inclusive {
+                return Err(self.error("An inclusive range '..=' needs an end".to_string()));
+            }
+            return Ok(Expr::new(ExprKind::Range(start, None, false), span));
+        }
+        let end = self.parse_binary(0)?;
+        Ok(Expr::new(ExprKind::Range(start, Some(Box::new(end)), inclusive), span))
+    }
+
+    /**
+     * Condition of "if", "while", etc. which is followed by a body, so it can't contain struct literals.
+     */
+    fn parse_condition(&mut self) -> Result<Expr, GiffiError> {
+        let no_struct_literal = self.no_struct_literal;
+        self.no_struct_literal = true;
+        let expr = self.parse_expression();
+        self.no_struct_literal = no_struct_literal;
+        expr
+    }
+
+    /**
+     * Expression inside of brackets or parentheses, where struct literals are allowed again.
+     */
+    fn parse_nested_expression(&mut self) -> Result<Expr, GiffiError> {
+        let no_struct_literal = self.no_struct_literal;
+        self.no_struct_literal = false;
+        let expr = self.parse_expression();
+        self.no_struct_literal = no_struct_literal;
+        expr
+    }
+
+    // https://en.wikipedia.org/wiki/Operator-precedence_parser
+    fn parse_binary(&mut self, prec: u8) -> Result<Expr, GiffiError> {
+        if prec >= MAX_PRECEDENCE {
+            return self.parse_primary();
+        }
+        if prec == UNARY_PRECEDENCE {
+            return self.parse_unary();
+        }
+
+        let mut lhs = self.parse_binary(prec + 1)?;
+        while let Some(LexerToken { token: LexerTokenType::Operator(op), .. }) = self.peek() {
+            let op = op.clone();
+            if Parser::get_precedence(&op) != Some(prec) {
+                break;
+            }
+            self.eat();
+
+            // The right hand side of a right associative operator can contain the same operator again,
+            // "2 ** 3 ** 2" is "2 ** (3 ** 2)", starting from the prefix operators allows "2 ** -1"
+            let rhs = if Parser::is_right_associative(&op) {
+                self.parse_binary(UNARY_PRECEDENCE)?
+            }
+            else {
+                self.parse_binary(prec + 1)?
+            };
+            let span = lhs.span;
+            lhs = Expr::new(ExprKind::Binary(Box::new(lhs), op, Box::new(rhs)), span);
+        }
+        Ok(lhs)
+    }
+
+    /**
+     * Prefix operators bind tighter than any binary operator except "**", "-a * b" is "(-a) * b"
+     * and "-2 ** 2" is "-(2 ** 2)". Indexing and calls bind tighter, "-a[0]" is "-(a[0])".
+     */
+    fn parse_unary(&mut self) -> Result<Expr, GiffiError> {
+        if let Some(LexerToken { token: LexerTokenType::Operator(op), line, column }) = self.peek() {
+            if op == "-" || op == "!" || op == "~" {
+                let op = op.clone();
+                let span = Span::new(*line, *column);
+                self.eat();
+                let operand = self.parse_unary()?;
+                return Ok(Expr::new(ExprKind::Unary(op, Box::new(operand)), span));
+            }
+        }
+        self.parse_binary(UNARY_PRECEDENCE + 1)
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, GiffiError> {
+        let token = self.eat_checked()?;
+        let span = Span::new(token.line, token.column);
+
+        let mut expr = match token.token {
+            LexerTokenType::Value(val) => {
+                Expr::new(ExprKind::Value(val), span)
+            }
+            LexerTokenType::Template(parts) => {
+                let parts = self.template_parts(parts, span)?;
+                Expr::new(ExprKind::Interpolation(parts), span)
+            }
+            LexerTokenType::Identifier(ident) => {
+                // Still need to determine between: "identifier, Struct { fields }"
+                if !self.no_struct_literal && self.peek_is(LexerTokenType::Symbol('{')) {
+                    self.eat().unwrap();
+                    let fields = self.struct_literal()?;
+                    Expr::new(ExprKind::StructLiteral(ident, fields), span)
                 }
-                else if let LexerTokenType::Operator(op) = &tk.token {
-                    match op.as_str() {
-                        "=" => { tokens.append(&mut self.variable_assignment(ident.clone())); }
-                        "(" => { tokens.append(&mut self.function_call(ident.clone())); }
-                        _ => { 
-                            self.error(format!("Invalid operator! {}", op));
-                        }
-                    }
+                else {
+                    Expr::new(ExprKind::Variable(ident), span)
                 }
-                
             }
-            else if token.token == LexerTokenType::NewLine {
-                self.eat();
+            LexerTokenType::Operator(op) if op == "(" => {
+                let expr = self.parse_nested_expression()?;
+                self.eat_expect(LexerTokenType::Operator(")".to_string()))?;
+                expr
+            }
+            LexerTokenType::Symbol('[') => {
+                let elements = self.array_literal()?;
+                Expr::new(ExprKind::Array(elements), span)
+            }
+            LexerTokenType::Symbol('{') => {
+                let entries = self.map_literal()?;
+                Expr::new(ExprKind::Map(entries), span)
+            }
+            LexerTokenType::Keyword(kw) if kw == "fn" => {
+                let args = self.function_arguments()?;
+                let body = self.parse_body()?;
+                Expr::new(ExprKind::Function(args, body), span)
+            }
+            LexerTokenType::Keyword(kw) if kw == "if" => {
+                self.if_expression(span)?
+            }
+            _ => { return Err(self.error(format!("Invalid syntax {:?}", token.token))); }
+        };
+
+        // Calls, indexing and field accesses can be chained "make_adder(1)(2)", "grid[y][x]", "p.pos.x", "p.length()"
+        loop {
+            if self.peek_is(LexerTokenType::Operator("(".to_string())) {
+                self.eat().unwrap();
+                let args = self.function_call_arguments()?;
+                expr = Expr::new(ExprKind::Call(Box::new(expr), args), span);
+            }
+            else if self.peek_is(LexerTokenType::Symbol('[')) {
+                self.eat().unwrap();
+                let index = self.parse_nested_expression()?;
+                self.eat_expect(LexerTokenType::Symbol(']'))?;
+                expr = Expr::new(ExprKind::Index(Box::new(expr), Box::new(index)), span);
             }
-            else 
-            {
-                self.error(format!("Invalid syntax found token {:?}", token));
+            else if self.peek_is(LexerTokenType::Symbol('.')) {
+                self.eat().unwrap();
+                let field = self.eat_identifier("Expected a field name after '.'")?;
+                expr = Expr::new(ExprKind::Field(Box::new(expr), field), span);
+            }
+            else {
+                break;
             }
         }
-        tokens
+        Ok(expr)
     }
 
     /**
-     * Turns an expression like [Identifier("foo"), Operator("+"), Int(5)] to
-     * [GetVariable("foo"), Operation("+"), Push(5)]
+     * Every "${...}" of a string is parsed by its own parser, it has to be exactly one expression.
      */
-    fn parse_expression(&mut self) -> Vec<ParserToken> {
-        assert!(self.is_expr);
-
-        let mut tokens = vec![];
-        // This is an array
-        if self.peek().is_some() && LexerTokenType::Symbol('[') == self.peek().unwrap().token {
-            self.eat_expect(LexerTokenType::Symbol('['));
-
-            let mut element_count = 0u32;
-            loop {
-                let mut array_element = self.eat_expr(vec![LexerTokenType::Symbol(','), LexerTokenType::Symbol(']')]);
-                tokens.append(&mut array_element);
-                
-                let peek = self.peek();
-                if peek.is_none() {
-                    self.error(format!("Expected ']' near '['"));
+    fn template_parts(&mut self, parts: Vec<TemplatePart>, span: Span) -> Result<Vec<Expr>, GiffiError> {
+        let mut exprs = vec![];
+        for part in parts {
+            match part {
+                TemplatePart::Text(text) => {
+                    exprs.push(Expr::new(ExprKind::Value(Value::Literal(text)), span));
                 }
-
-                if let LexerTokenType::Symbol(symbol) = &peek.unwrap().token {
-                    if symbol == &',' {
-                        self.eat_expect(LexerTokenType::Symbol(','));
-                        element_count += 1;
-                    }
-                    else if symbol == &']' {
-                        self.eat_expect(LexerTokenType::Symbol(']'));
-                        break;
-                    }
-                    else {
-                        self.error(format!("Expected ']' near '['"));
-                    }
+                TemplatePart::Code(tokens) => {
+                    let mut parser = Parser::new(tokens);
+                    exprs.push(parser.parse_expression()?);
+                    parser.eat_expect(LexerTokenType::Eof)?;
                 }
             }
+        }
+        Ok(exprs)
+    }
 
-            // element_count is 1 off, because it counts ','s so we add 1 here, if there was atleast 1 argument.
-            element_count += if tokens.len() > 0 { 1 } else { 0 };
-            tokens.push(ParserToken::MakeArray(element_count));
-            return tokens;
+    /**
+     * Expects the '[' to be already eaten.
+     */
+    fn array_literal(&mut self) -> Result<Vec<Expr>, GiffiError> {
+        let mut elements = vec![];
+        if self.peek_is(LexerTokenType::Symbol(']')) {
+            self.eat().unwrap();
+            return Ok(elements);
         }
 
-        // Other expression
-        'parse_loop : loop {
-            let peek = self.peek();
-            if peek.is_none() {
-                break 'parse_loop;
+        loop {
+            elements.push(self.parse_nested_expression()?);
+
+            let next = self.eat_checked()?.token;
+            if next == LexerTokenType::Symbol(',') {
+                continue;
             }
-            let token = self.eat().unwrap().token;
-            
-            match token {
-                LexerTokenType::Identifier(ident) => {
-                    let next = self.eat();
-                    if next.is_none() {
-                        tokens.push(ParserToken::GetVariable(ident));
-                        break;
-                    }
+            else if next == LexerTokenType::Symbol(']') {
+                break;
+            }
+            return Err(self.error(format!("Expected ']' near '['")));
+        }
+        Ok(elements)
+    }
 
-                    // Still need to determine between: "identifier, fncall(args), array[0]""
-                    let next_token = next.unwrap().token;
-                    match next_token {
-                        LexerTokenType::Operator(op) => {
-                            match op.as_str() {
-                                "(" => { 
-                                    tokens.append(&mut self.function_call(ident)); 
-                                }
-                                _ => { 
-                                    tokens.push(ParserToken::GetVariable(ident));
-                                    tokens.push(ParserToken::Operation(op));
-                                }
-                            }
-                        }
-                        // Indexing into array
-                        LexerTokenType::Symbol(symbol) => {
-                            if symbol != '[' {
-                                self.error(format!("Unexpected '['"));
-                            }
-                            let argument = self.eat_expr(vec![LexerTokenType::Symbol(']')]);
-                            self.eat_expect(LexerTokenType::Symbol(']'));
-                            tokens.push(ParserToken::GetVariableArrayElement(ident, argument));
-                        }
-                        _ => { 
-                            self.error(format!("Unexpected {:?} after expression!", next_token));
-                        }
-                    }
-                }
-                LexerTokenType::Operator(op) => {
-                    tokens.push(ParserToken::Operation(op));
-                },
-                LexerTokenType::Value(val) => {
-                    tokens.push(ParserToken::Push(val));
-                } 
-                _ => { self.error(format!("Invalid syntax {:?}", token)); }
+    /**
+     * Parses "{ "key": <expr>, ... }", expects the '{' to be already eaten.
+     */
+    fn map_literal(&mut self) -> Result<Vec<(String, Expr)>, GiffiError> {
+        let mut entries = vec![];
+        if self.peek_is(LexerTokenType::Symbol('}')) {
+            self.eat().unwrap();
+            return Ok(entries);
+        }
+
+        loop {
+            let key = self.eat_checked()?.token;
+            let LexerTokenType::Value(Value::Literal(key)) = key else {
+                return Err(self.error(format!("Expected a string as the key of a map, got {:?} instead!", key)));
+            };
+            self.eat_expect(LexerTokenType::Symbol(':'))?;
+            entries.push((key, self.parse_nested_expression()?));
+
+            let next = self.eat_checked()?.token;
+            if next == LexerTokenType::Symbol(',') {
+                continue;
             }
+            else if next == LexerTokenType::Symbol('}') {
+                break;
+            }
+            return Err(self.error(format!("Expected '}}' near '{{'")));
         }
-        tokens
+        Ok(entries)
     }
 
-    #[must_use]
-    fn import_keyword(&mut self) -> Vec<ParserToken> {
+    /**
+     * Parses "<field>: <expr>, ... }", expects the '{' to be already eaten.
+     */
+    fn struct_literal(&mut self) -> Result<Vec<(String, Expr)>, GiffiError> {
+        let mut fields = vec![];
+        if self.peek_is(LexerTokenType::Symbol('}')) {
+            self.eat().unwrap();
+            return Ok(fields);
+        }
+
+        loop {
+            let field = self.eat_identifier("Expected a field name in the struct literal")?;
+            self.eat_expect(LexerTokenType::Symbol(':'))?;
+            fields.push((field, self.parse_nested_expression()?));
+
+            let next = self.eat_checked()?.token;
+            if next == LexerTokenType::Symbol(',') {
+                continue;
+            }
+            else if next == LexerTokenType::Symbol('}') {
+                break;
+            }
+            return Err(self.error(format!("Expected '}}' at the end of the struct literal")));
+        }
+        Ok(fields)
+    }
+
+    fn get_precedence(op: &String) -> Option<u8> {
+        match op.as_str() {
+            // Same order as in C
+            "||" => {
+                return Some(0u8);
+            },
+            "&&" => {
+                return Some(1u8);
+            },
+            "|" => {
+                return Some(2u8);
+            },
+            "^" => {
+                return Some(3u8);
+            },
+            "&" => {
+                return Some(4u8);
+            },
+            "==" | "!=" => {
+                return Some(5u8);
+            }
+            "<" | ">" | "<=" | ">=" => {
+                return Some(6u8);
+            },
+            "<<" | ">>" => {
+                return Some(7u8);
+            },
+            "+" | "-" => {
+                return Some(8u8);
+            }
+            "*" | "/" | "%" => {
+                return Some(9u8);
+            }
+            "**" => {
+                return Some(11u8);
+            }
+            _ => {
+                return None;
+            }
+        }
+    }
+
+    fn is_right_associative(op: &String) -> bool {
+        op == "**"
+    }
+
+    fn import_keyword(&mut self) -> Result<Stmt, GiffiError> {
         // Syntax "<keyword->import> <literal><semicolon>"
-        self.eat_expect(LexerTokenType::Keyword("import".to_string()));
-        
+        let keyword = self.eat_expect(LexerTokenType::Keyword("import".to_string()))?;
+        let span = Span::new(keyword.line, keyword.column);
+
         let library = self.eat();
-        if library.is_some() {
-            if let LexerTokenType::Value(library_val) = library.unwrap().token {
-                if let Value::Literal(library_name) = library_val {
-                    self.eat_expect(LexerTokenType::Symbol(';'));
-                    return vec![ParserToken::Import(library_name.clone())];
-                }
-            }
+        if let Some(LexerToken { token: LexerTokenType::Value(Value::Literal(library_name)), .. }) = library {
+            self.eat_expect(LexerTokenType::Symbol(';'))?;
+            return Ok(Stmt::new(StmtKind::Import(library_name), span));
         }
-        self.error("Expected Library as String after \"import\" got EOF instead!".to_string());
+        return Err(self.error("Expected Library as String after \"import\" got EOF instead!".to_string()));
     }
 
-    #[must_use]
-    fn if_statement(&mut self) -> Vec<ParserToken> {
-        self.eat_expect(LexerTokenType::Keyword("if".to_string()));
+    fn if_statement(&mut self) -> Result<Stmt, GiffiError> {
+        let keyword = self.eat_expect(LexerTokenType::Keyword("if".to_string()))?;
+        let span = Span::new(keyword.line, keyword.column);
 
-        // If comparision 
-        let mut expr = self.eat_expr(vec![LexerTokenType::Symbol('{')]);
-        if expr.len() == 0 {
-            self.error("Expecte an comparision after 'if' statement!".to_string());
+        // If comparision
+        if self.peek_is(LexerTokenType::Symbol('{')) {
+            return Err(self.error("Expecte an comparision after 'if' statement!".to_string()));
         }
-        
+        let expr = self.parse_condition()?;
+
         // If(true) body
-        self.eat_expect(LexerTokenType::Symbol('{'));
-        let if_body = self.parse_until(LexerTokenType::Symbol('}'));
-        self.eat_expect(LexerTokenType::Symbol('}'));
+        let if_body = self.parse_body()?;
 
         // else body, if followed by an else statement
         let mut else_body = vec![];
-        let peek = self.peek();
-        if let Some(tk) = peek {
-            if let LexerTokenType::Keyword(kw) = &tk.token {
-                if kw == "else" {
-                    self.eat_expect(LexerTokenType::Keyword("else".to_string()));
-
-                    let next = self.peek().expect("Expected '{' or 'if' efter keyword else");
-                    if LexerTokenType::Keyword("if".to_string()) == next.token {
-                        // "else if" body
-                        else_body = self.if_statement();
-                    }
-                    else {
-                        // else body
-                        self.eat_expect(LexerTokenType::Symbol('{'));
-                        else_body = self.parse_until(LexerTokenType::Symbol('}'));
-                        self.eat_expect(LexerTokenType::Symbol('}'));
-                    }
+        if self.peek_is(LexerTokenType::Keyword("else".to_string())) {
+            self.eat_expect(LexerTokenType::Keyword("else".to_string()))?;
 
-                }
+            if self.peek_is(LexerTokenType::Keyword("if".to_string())) {
+                // "else if" body
+                else_body = vec![self.if_statement()?];
+            }
+            else {
+                // else body
+                else_body = self.parse_body()?;
             }
         }
 
-        // Tokens
-        let mut tokens = vec![];
-        tokens.append(&mut expr);
-        tokens.push(
-            ParserToken::If(if_body, else_body)
-        );
-        tokens
+        Ok(Stmt::new(StmtKind::If(expr, if_body, else_body), span))
     }
 
-    #[must_use]
-    fn while_statement(&mut self) -> Vec<ParserToken> {
-        self.eat_expect(LexerTokenType::Keyword("while".to_string()));
+    fn while_statement(&mut self) -> Result<Stmt, GiffiError> {
+        let keyword = self.eat_expect(LexerTokenType::Keyword("while".to_string()))?;
+        let span = Span::new(keyword.line, keyword.column);
 
-        // While comparision 
-        let expr = self.eat_expr(vec![LexerTokenType::Symbol('{')]);
-        if expr.len() == 0 {
-            panic!("Expecte an expression after 'while' statement!");
+        // While comparision
+        if self.peek_is(LexerTokenType::Symbol('{')) {
+            return Err(self.error("Expecte an expression after 'while' statement!".to_string()));
         }
-        
-        // If(true) body
-        self.eat_expect(LexerTokenType::Symbol('{'));
-        let if_body = self.parse_until(LexerTokenType::Symbol('}'));
-        self.eat_expect(LexerTokenType::Symbol('}'));
+        let expr = self.parse_condition()?;
+        let body = self.parse_body()?;
 
-        // Tokens
-        let mut tokens = vec![];
-        tokens.push(
-            ParserToken::While(expr, if_body)
-        );
-        tokens
+        Ok(Stmt::new(StmtKind::While(expr, body), span))
     }
 
-    #[must_use]
-    fn function_return(&mut self) -> Vec<ParserToken> {
-        self.eat_expect(LexerTokenType::Keyword("return".to_string()));
-        let expr = self.eat_expr(vec![LexerTokenType::Symbol(';')]);
-        self.eat_expect(LexerTokenType::Symbol(';'));
+    /**
+     * "if" is already eaten, "<condition> { <expr> } else { <expr> }" is left.
+     * The else branch is required and can be another if expression.
+     */
+    fn if_expression(&mut self, span: Span) -> Result<Expr, GiffiError> {
+        let check = self.parse_condition()?;
+        let then = self.expression_block()?;
+        if !self.peek_is(LexerTokenType::Keyword("else".to_string())) {
+            return Err(self.error("An 'if' expression needs an 'else' branch".to_string()));
+        }
+        self.eat().unwrap();
 
-        let mut tokens;
-        if expr.len() > 0 {
-            tokens = expr;
+        let otherwise = if self.peek_is(LexerTokenType::Keyword("if".to_string())) {
+            let keyword = self.eat().unwrap();
+            self.if_expression(Span::new(keyword.line, keyword.column))?
         }
-        // Implicit "return;" -> "return null;"
         else {
-            tokens = vec![ParserToken::Push(Value::Null)];
+            self.expression_block()?
+        };
+        Ok(Expr::new(ExprKind::If(Box::new(check), Box::new(then), Box::new(otherwise)), span))
+    }
+
+    /**
+     * "{ <expr> }" of an if expression
+     */
+    fn expression_block(&mut self) -> Result<Expr, GiffiError> {
+        self.eat_expect(LexerTokenType::Symbol('{'))?;
+        let expr = self.parse_nested_expression()?;
+        self.eat_expect(LexerTokenType::Symbol('}'))?;
+        Ok(expr)
+    }
+
+    /**
+     * "try { } catch (<name>) { } finally { }", either the catch or the finally can be left out
+     */
+    fn try_statement(&mut self) -> Result<Stmt, GiffiError> {
+        let keyword = self.eat_expect(LexerTokenType::Keyword("try".to_string()))?;
+        let span = Span::new(keyword.line, keyword.column);
+        let body = self.parse_body()?;
+
+        let mut catch = None;
+        if self.peek_is(LexerTokenType::Keyword("catch".to_string())) {
+            self.eat().unwrap();
+            self.eat_expect(LexerTokenType::Operator("(".to_string()))?;
+            let name = self.eat_identifier("Expected the name of the caught error")?;
+            self.eat_expect(LexerTokenType::Operator(")".to_string()))?;
+            catch = Some((name, self.parse_body()?));
+        }
+        let mut finally = None;
+        if self.peek_is(LexerTokenType::Keyword("finally".to_string())) {
+            self.eat().unwrap();
+            finally = Some(self.parse_body()?);
         }
-        tokens.push(ParserToken::Return());
-        tokens
+        if catch.is_none() && finally.is_none() {
+            return Err(self.error("Expected 'catch' or 'finally' after a 'try' block".to_string()));
+        }
+        Ok(Stmt::new(StmtKind::Try(body, catch, finally), span))
     }
 
-    #[must_use]
-    fn array_assignment(&mut self, var_name: String) -> Vec<ParserToken> {
-        // Get Index
-        let idx_expr = self.eat_expr(vec![LexerTokenType::Symbol(']')]);
-        self.eat_expect(LexerTokenType::Symbol(']'));
+    /**
+     * "match <expr> { <pattern> if <guard> => <body>, ... }", the guard is optional.
+     * The body is a block or a single assignment or expression.
+     */
+    fn match_statement(&mut self) -> Result<Stmt, GiffiError> {
+        let keyword = self.eat_expect(LexerTokenType::Keyword("match".to_string()))?;
+        let span = Span::new(keyword.line, keyword.column);
+
+        let value = self.parse_condition()?;
+        self.eat_expect(LexerTokenType::Symbol('{'))?;
+        let mut arms = vec![];
+        while !self.peek_is(LexerTokenType::Symbol('}')) {
+            let token = self.peek().ok_or(self.error("Got unexpected EOF".to_string()))?;
+            let arm_span = Span::new(token.line, token.column);
+            let pattern = self.parse_pattern()?;
+            let mut guard = None;
+            if self.peek_is(LexerTokenType::Keyword("if".to_string())) {
+                self.eat().unwrap();
+                guard = Some(self.parse_expression()?);
+            }
+            self.eat_expect(LexerTokenType::Operator("=>".to_string()))?;
+
+            // The ',' is optional after a block
+            let body;
+            if self.peek_is(LexerTokenType::Symbol('{')) {
+                body = self.parse_body()?;
+                if self.peek_is(LexerTokenType::Symbol(',')) {
+                    self.eat().unwrap();
+                }
+            }
+            else {
+                let token = self.peek().ok_or(self.error("Got unexpected EOF".to_string()))?;
+                let statement_span = Span::new(token.line, token.column);
+                body = vec![self.simple_statement(statement_span)?];
+                if !self.peek_is(LexerTokenType::Symbol('}')) {
+                    self.eat_expect(LexerTokenType::Symbol(','))?;
+                }
+            }
+            arms.push(MatchArm { pattern: pattern, guard: guard, body: body, span: arm_span });
+        }
+        self.eat_expect(LexerTokenType::Symbol('}'))?;
 
-        // Get Assigment
-        self.eat_expect(LexerTokenType::Operator("=".to_string()));
-        let mut assign_expr = self.eat_expr(vec![LexerTokenType::Symbol(';')]);
-        self.eat_expect(LexerTokenType::Symbol(';'));
+        Ok(Stmt::new(StmtKind::Match(value, arms), span))
+    }
 
-        let mut tokens = idx_expr;
-        tokens.append(&mut assign_expr);
-        tokens.push(ParserToken::StoreVariableArrayElement(var_name));
-        tokens
+    /**
+     * Alternatives "1 | 2" of single patterns
+     */
+    fn parse_pattern(&mut self) -> Result<Pattern, GiffiError> {
+        let mut alternatives = vec![self.parse_single_pattern()?];
+        while self.peek_is(LexerTokenType::Operator("|".to_string())) {
+            self.eat().unwrap();
+            alternatives.push(self.parse_single_pattern()?);
+        }
+        if alternatives.len() == 1 {
+            return Ok(alternatives.pop().unwrap());
+        }
+        Ok(Pattern::Or(alternatives))
     }
 
-    #[must_use]
-    fn variable_assignment(&mut self, var_name: String) -> Vec<ParserToken> {
-        let mut tokens = self.eat_expr(vec![LexerTokenType::Symbol(';')]);
-        self.eat_expect(LexerTokenType::Symbol(';'));
-        tokens.push(ParserToken::StoreVariable(var_name));
-        tokens
+    /**
+     * Literal "-1", wildcard "_", binding "x", array "[x, 1, ...rest]" or fields "{x, y}"
+     */
+    fn parse_single_pattern(&mut self) -> Result<Pattern, GiffiError> {
+        match self.eat_checked()?.token {
+            LexerTokenType::Value(value) => {
+                return Ok(Pattern::Value(value));
+            }
+            LexerTokenType::Operator(op) if op == "-" => {
+                match self.eat_checked()?.token {
+                    LexerTokenType::Value(Value::Int(i)) => { return Ok(Pattern::Value(Value::Int(-i))); }
+                    LexerTokenType::Value(Value::Float(f)) => { return Ok(Pattern::Value(Value::Float(-f))); }
+                    _ => { return Err(self.error("Expected a number after '-' in a pattern".to_string())); }
+                }
+            }
+            LexerTokenType::Identifier(name) => {
+                if name == "_" {
+                    return Ok(Pattern::Wildcard);
+                }
+                return Ok(Pattern::Binding(name));
+            }
+            LexerTokenType::Symbol('[') => {
+                let mut elements = vec![];
+                while !self.peek_is(LexerTokenType::Symbol(']')) {
+                    if self.peek_is(LexerTokenType::Operator("...".to_string())) {
+                        self.eat().unwrap();
+                        let name = self.eat_identifier("Expected a name after '...'")?;
+                        elements.push(Pattern::Rest(if name == "_" { None } else { Some(name) }));
+                        if !self.peek_is(LexerTokenType::Symbol(']')) {
+                            return Err(self.error("'...' has to be the last element of an array pattern".to_string()));
+                        }
+                        continue;
+                    }
+                    elements.push(self.parse_pattern()?);
+                    if !self.peek_is(LexerTokenType::Symbol(']')) {
+                        self.eat_expect(LexerTokenType::Symbol(','))?;
+                    }
+                }
+                self.eat_expect(LexerTokenType::Symbol(']'))?;
+                return Ok(Pattern::Array(elements));
+            }
+            LexerTokenType::Symbol('{') => {
+                let mut fields = vec![];
+                while !self.peek_is(LexerTokenType::Symbol('}')) {
+                    fields.push(self.eat_identifier("Expected a field name in the pattern")?);
+                    if !self.peek_is(LexerTokenType::Symbol('}')) {
+                        self.eat_expect(LexerTokenType::Symbol(','))?;
+                    }
+                }
+                self.eat_expect(LexerTokenType::Symbol('}'))?;
+                return Ok(Pattern::Fields(fields));
+            }
+            token => {
+                return Err(self.error(format!("Expected a pattern, got {:?}", token)));
+            }
+        }
     }
 
-    #[must_use]
-    fn variable_decleration(&mut self) -> Vec<ParserToken> {
+    /**
+     * "for (<init>; <condition>; <step>) { }" or "for <name>[, <name>] in <expr> { }", all parts of the C-style one are optional.
+     */
+    fn for_statement(&mut self) -> Result<Stmt, GiffiError> {
+        let keyword = self.eat_expect(LexerTokenType::Keyword("for".to_string()))?;
+        let span = Span::new(keyword.line, keyword.column);
+
+        if !self.peek_is(LexerTokenType::Operator("(".to_string())) {
+            let mut names = vec![self.eat_identifier("Expected a variable name after 'for'")?];
+            if self.peek_is(LexerTokenType::Symbol(',')) {
+                self.eat().unwrap();
+                names.push(self.eat_identifier("Expected a second variable name after ','")?);
+            }
+            self.eat_expect(LexerTokenType::Keyword("in".to_string()))?;
+            let iterable = self.parse_condition()?;
+            let body = self.parse_body()?;
+            return Ok(Stmt::new(StmtKind::ForIn(names, iterable, body), span));
+        }
+        self.eat().unwrap();
+
+        // Init, "let" and plain statements eat the ';' themselves
+        let mut init = None;
+        if self.peek_is(LexerTokenType::Symbol(';')) {
+            self.eat().unwrap();
+        }
+        else {
+            let statement = self.parse_statement()?;
+            match &statement.kind {
+                StmtKind::Let(..) | StmtKind::Assign(..) | StmtKind::CompoundAssign(..) | StmtKind::Expr(_) => {},
+                _ => { return Err(self.error("Expected a variable declaration or an assignment in a 'for' loop".to_string())); }
+            }
+            init = Some(Box::new(statement));
+        }
+
+        let mut condition = None;
+        if !self.peek_is(LexerTokenType::Symbol(';')) {
+            condition = Some(self.parse_expression()?);
+        }
+        self.eat_expect(LexerTokenType::Symbol(';'))?;
+
+        let mut step = None;
+        if !self.peek_is(LexerTokenType::Operator(")".to_string())) {
+            let step_span = self.peek().map(|tk| Span::new(tk.line, tk.column)).unwrap_or(span);
+            step = Some(Box::new(self.simple_statement(step_span)?));
+        }
+        self.eat_expect(LexerTokenType::Operator(")".to_string()))?;
+
+        let body = self.parse_body()?;
+        Ok(Stmt::new(StmtKind::For(init, condition, step, body), span))
+    }
+
+    /**
+     * Parses "{ <statements> }"
+     */
+    fn parse_body(&mut self) -> Result<Vec<Stmt>, GiffiError> {
+        self.eat_expect(LexerTokenType::Symbol('{'))?;
+        let body = self.parse_until(LexerTokenType::Symbol('}'))?;
+        self.eat_expect(LexerTokenType::Symbol('}'))?;
+        Ok(body)
+    }
+
+    fn function_return(&mut self) -> Result<Stmt, GiffiError> {
+        let keyword = self.eat_expect(LexerTokenType::Keyword("return".to_string()))?;
+        let span = Span::new(keyword.line, keyword.column);
+
+        // Implicit "return;" -> "return null;"
+        if self.peek_is(LexerTokenType::Symbol(';')) {
+            self.eat_expect(LexerTokenType::Symbol(';'))?;
+            return Ok(Stmt::new(StmtKind::Return(None), span));
+        }
+
+        let expr = self.parse_expression()?;
+        self.eat_expect(LexerTokenType::Symbol(';'))?;
+        Ok(Stmt::new(StmtKind::Return(Some(expr)), span))
+    }
+
+    /**
+     * Target is already parsed, "= <expr>" or "+= <expr>" etc. is left.
+     */
+    fn variable_assignment(&mut self, target: Expr) -> Result<Stmt, GiffiError> {
+        let span = target.span;
+        if !Parser::is_assignable(&target) {
+            return Err(self.error(format!("Cannot assign into {:?}", target.kind)));
+        }
+
+        let LexerTokenType::Operator(op) = self.eat_checked()?.token else {
+            return Err(self.error("Expected an assignment".to_string()));
+        };
+        let value = self.parse_expression()?;
+
+        if let Some(binary_op) = Parser::compound_operator(&op) {
+            if let ExprKind::Array(_) = target.kind {
+                return Err(self.error(format!("Cannot use '{}' on multiple targets", op)));
+            }
+            return Ok(Stmt::new(StmtKind::CompoundAssign(target, binary_op, value), span));
+        }
+        Ok(Stmt::new(StmtKind::Assign(target, value), span))
+    }
+
+    /**
+     * Variables, elements, fields and arrays of them "[a, b[0]] = [b[0], a];"
+     */
+    fn is_assignable(target: &Expr) -> bool {
+        match &target.kind {
+            ExprKind::Variable(_) | ExprKind::Index(..) | ExprKind::Field(..) => { return true; }
+            ExprKind::Array(elements) => { return elements.iter().all(Parser::is_assignable); }
+            _ => { return false; }
+        }
+    }
+
+    /**
+     * "+=" -> "+", None if it's not a compound assignment
+     */
+    fn compound_operator(op: &String) -> Option<String> {
+        match op.as_str() {
+            "+=" | "-=" | "*=" | "/=" | "%=" | "**=" | "&=" | "|=" | "^=" | "<<=" | ">>=" => {
+                return Some(op[..op.len() - 1].to_string());
+            }
+            _ => {
+                return None;
+            }
+        }
+    }
+
+    fn variable_decleration(&mut self) -> Result<Stmt, GiffiError> {
         // eat "let" keyword
-        self.eat();
-    
+        let keyword = self.eat_checked()?;
+        let span = Span::new(keyword.line, keyword.column);
+
+        // Destructuring "let [a, b] = ...;", "let {x, y} = ...;"
+        if self.peek_is(LexerTokenType::Symbol('[')) || self.peek_is(LexerTokenType::Symbol('{')) {
+            let pattern = self.parse_single_pattern()?;
+            if !Parser::is_destructuring_pattern(&pattern) {
+                return Err(self.error("'let' can only destructure into variables, arrays and fields".to_string()));
+            }
+            self.eat_expect(LexerTokenType::Operator("=".to_string()))?;
+            let expr = self.parse_expression()?;
+            self.eat_expect(LexerTokenType::Symbol(';'))?;
+            return Ok(Stmt::new(StmtKind::LetPattern(pattern, expr), span));
+        }
+
         // identifier
-        let tk_identifier = self.eat().expect("expected an identifier after 'let' keyword").token;
+        let tk_identifier = self.eat_checked()?.token;
         if let LexerTokenType::Identifier(identifier) = tk_identifier {
             // Syntax
-            self.eat_expect(LexerTokenType::Operator("=".to_string()));
-            let mut expr = self.eat_expr(vec![LexerTokenType::Symbol(';')]);
-            self.eat_expect(LexerTokenType::Symbol(';'));
-            
-            // Tokens        
-            if expr.len() == 0 {
-                self.error("Expected an expression after '=', before ';'".to_string());
+            self.eat_expect(LexerTokenType::Operator("=".to_string()))?;
+            if self.peek_is(LexerTokenType::Symbol(';')) {
+                return Err(self.error("Expected an expression after '=', before ';'".to_string()));
             }
-            let mut tokens = vec![];
-            tokens.append(&mut expr);
-            tokens.push(ParserToken::DeclareVariable(identifier.clone()));
-    
-            return tokens;
+            let expr = self.parse_expression()?;
+            self.eat_expect(LexerTokenType::Symbol(';'))?;
+
+            return Ok(Stmt::new(StmtKind::Let(identifier, expr), span));
         }
-        self.error("expected an identifier after 'let' keyword".to_string());
+        return Err(self.error("expected an identifier after 'let' keyword".to_string()));
     }
 
-    #[must_use]
-    fn function_decleration(&mut self) -> Vec<ParserToken> {
+    /**
+     * Patterns which can't fail to match, literals and alternatives can.
+     */
+    fn is_destructuring_pattern(pattern: &Pattern) -> bool {
+        match pattern {
+            Pattern::Wildcard | Pattern::Binding(_) | Pattern::Rest(_) | Pattern::Fields(_) => { return true; }
+            Pattern::Array(elements) => { return elements.iter().all(Parser::is_destructuring_pattern); }
+            Pattern::Value(_) | Pattern::Or(_) => { return false; }
+        }
+    }
+
+    fn function_decleration(&mut self) -> Result<Stmt, GiffiError> {
         // eat "fn" keyword
-        self.eat();
+        let keyword = self.eat_checked()?;
+        let span = Span::new(keyword.line, keyword.column);
 
         // identifier
-        let tk_identifier = self.eat().expect("expected an identifier after 'fn' keyword").token;
+        let tk_identifier = self.eat_checked()?.token;
         if let LexerTokenType::Identifier(fn_name) = tk_identifier {
-            // eat operator '('
-            self.eat_expect(LexerTokenType::Operator("(".to_string()));
+            let args = self.function_arguments()?;
+            let fn_body = self.parse_body()?;
+            return Ok(Stmt::new(StmtKind::Function(fn_name, args, fn_body), span));
+        }
+        return Err(self.error("expected an identifier after 'fn' keyword".to_string()));
+    }
 
-            // get argument names
-            let mut fn_tokens: Vec<ParserToken> = vec![];
-            'args : loop {
-                let tk = self.eat().expect("Invalid function decleration").token;
+    fn struct_decleration(&mut self) -> Result<Stmt, GiffiError> {
+        // Syntax "struct <identifier> { <field>, <field> }"
+        let keyword = self.eat_expect(LexerTokenType::Keyword("struct".to_string()))?;
+        let span = Span::new(keyword.line, keyword.column);
+        let struct_name = self.eat_identifier("expected an identifier after 'struct' keyword")?;
 
-                if let LexerTokenType::Identifier(arg_identifier) = tk {
-                    // When calling the function the values are pushed to the stack, here just use them to declare 
-                    // variables out of them (btw this has to be done in a reverse order, hence the reverse after the loop)
-                    fn_tokens.push(ParserToken::DeclareVariable(arg_identifier));
+        self.eat_expect(LexerTokenType::Symbol('{'))?;
+        let mut fields: Vec<String> = vec![];
+        'fields : loop {
+            let tk = self.eat_checked()?.token;
 
-                    let next  = self.eat().expect("Invalid function decleration").token;
-                    if next == LexerTokenType::Symbol(',') {
-                        continue;
-                    }
-                    else if next == LexerTokenType::Operator(")".to_string()) {
-                        break 'args;
-                    }
-                    self.error("Syntax error".to_string());
+            if let LexerTokenType::Identifier(field) = tk {
+                if fields.contains(&field) {
+                    return Err(self.error(format!("Field '{}' is declared twice", field)));
                 }
-                else if tk == LexerTokenType::Operator(")".to_string()) {
-                    break 'args;
+                fields.push(field);
+
+                let next = self.eat_checked()?.token;
+                if next == LexerTokenType::Symbol(',') {
+                    continue;
                 }
-                else {
-                    self.error("Syntax error".to_string());
+                else if next == LexerTokenType::Symbol('}') {
+                    break 'fields;
                 }
+                return Err(self.error("Syntax error".to_string()));
+            }
+            else if tk == LexerTokenType::Symbol('}') {
+                break 'fields;
+            }
+            else {
+                return Err(self.error("Syntax error".to_string()));
             }
-            fn_tokens.reverse();
+        }
+        Ok(Stmt::new(StmtKind::Struct(struct_name, fields), span))
+    }
 
-            self.eat_expect(LexerTokenType::Symbol('{'));
-            let mut fn_body = self.parse_until(LexerTokenType::Symbol('}'));
-            self.eat_expect(LexerTokenType::Symbol('}'));
+    fn impl_block(&mut self) -> Result<Stmt, GiffiError> {
+        // Syntax "impl <identifier> { <functions> }"
+        let keyword = self.eat_expect(LexerTokenType::Keyword("impl".to_string()))?;
+        let span = Span::new(keyword.line, keyword.column);
+        let struct_name = self.eat_identifier("expected an identifier after 'impl' keyword")?;
 
-            fn_tokens.append(&mut fn_body);
-            
-            // push tokens
-            return vec![(ParserToken::DeclareFunction(fn_name, fn_tokens))];
+        let methods = self.parse_body()?;
+        for method in &methods {
+            if !matches!(method.kind, StmtKind::Function(..)) {
+                return Err(GiffiError::new(ErrorKind::Parse, "Only functions can be declared in an 'impl' block".to_string(), method.span));
+            }
         }
-        self.error("expected an identifier after 'fn' keyword".to_string());
+        Ok(Stmt::new(StmtKind::Impl(struct_name, methods), span))
     }
 
-    #[must_use]
-    fn function_call(&mut self, fn_name: String) -> Vec<ParserToken> {
-        let mut arg_tokens = vec![];
+    /**
+     * Parses the argument names "(a, b)" of a function decleration.
+     */
+    fn function_arguments(&mut self) -> Result<Vec<String>, GiffiError> {
+        // eat operator '('
+        self.eat_expect(LexerTokenType::Operator("(".to_string()))?;
+
+        // get argument names
+        let mut args: Vec<String> = vec![];
         'args : loop {
-            let tk = &self.peek().expect(format!("Invalid function call '{}()'", fn_name).as_str()).token;
+            let tk = self.eat_checked()?.token;
 
-            if tk == &LexerTokenType::Operator(")".to_string()) {
-                self.eat().unwrap();
-                break 'args;
-            }
-            else {
-                let mut expr = self.eat_expr(
-                    vec![
-                        LexerTokenType::Symbol(','),
-                        LexerTokenType::Operator(")".to_string()),
-                    ]
-                );
-                arg_tokens.append(&mut expr);
-
-                let next = self.eat().expect("syntax error").token;
+            if let LexerTokenType::Identifier(arg_identifier) = tk {
+                args.push(arg_identifier);
+
+                let next = self.eat_checked()?.token;
                 if next == LexerTokenType::Symbol(',') {
                     continue;
                 }
                 else if next == LexerTokenType::Operator(")".to_string()) {
                     break 'args;
                 }
-                else {
-                    self.error("Syntax error".to_string());
-                }
+                return Err(self.error("Syntax error".to_string()));
+            }
+            else if tk == LexerTokenType::Operator(")".to_string()) {
+                break 'args;
+            }
+            else {
+                return Err(self.error("Syntax error".to_string()));
             }
         }
-
-        if !self.is_expr {
-            self.eat_expect(LexerTokenType::Symbol(';'));
-        }
-        
-        let mut tokens = vec![];
-        tokens.push(ParserToken::Call(fn_name, arg_tokens));
-        return tokens;
+        Ok(args)
     }
 
     /**
-     * Terminator is used to determine when the expression is suppost to end, terminator doesn't get eaten. e.g: 
-     * "LexerTokenType::Symbol(';')" for "let x = 2+2;"
-     * "LexerTokenType::Symbol(',')" for "fn foo(2+2+2, 0)"
-     * "LexerTokenType::Operator(')')" for "fn foo(2+2+2)" // this is going to be a fucking problem, lol.
+     * Expects the '(' to be already eaten, eats the closing ')'.
      */
-    fn eat_until(&mut self, terminator: Vec<LexerTokenType>) -> VecDeque<LexerToken> {
-        let mut out_tks = VecDeque::<LexerToken>::new();
-
-        let mut scopes: Vec<char> = vec![]; // '(' gets pushed in, and ')' pushes them out.
-        'get_tokens: loop {
-            let peeked = self.peek();
-            if peeked.is_none() {
-                if terminator.contains(&LexerTokenType::Eof) {
-                    break 'get_tokens;
-                }
-                self.error(format!("Expected '{:?}' got EOF instead!", terminator));
-            }
+    fn function_call_arguments(&mut self) -> Result<Vec<Expr>, GiffiError> {
+        let mut args = vec![];
+        if self.peek_is(LexerTokenType::Operator(")".to_string())) {
+            self.eat().unwrap();
+            return Ok(args);
+        }
 
-            // Don't eat before this, we don't want to eat the terminator.
-            if scopes.len() == 0 && terminator.contains(&peeked.unwrap().token) {
-                break 'get_tokens;
+        'args : loop {
+            if self.peek().is_none() {
+                return Err(self.error("Invalid function call, expected ')'".to_string()));
             }
+            args.push(self.parse_nested_expression()?);
 
-            let token = self.eat().unwrap();
-            if let LexerTokenType::Operator(op) = &token.token {
-                match op.as_str() {
-                    "(" => {
-                        scopes.push('(');
-                    },
-                    ")" => {
-                        let popped = scopes.pop().expect("No matching ']' for '['");
-                        if popped != '(' {
-                            self.error(format!("Expected ')' for '(', but got {} instead!", popped));
-                        }
-                    }
-                    _ => {}
-                }
+            let next = self.eat_checked()?.token;
+            if next == LexerTokenType::Symbol(',') {
+                continue;
             }
-
-            // Arrays
-            if let LexerTokenType::Symbol(s) = &token.token {
-                match s.clone() {
-                    '[' => {
-                        scopes.push(s.clone());
-                    },
-                    ']' => {
-                        let popped = scopes.pop().expect("No matching ']' for '['");
-                        if popped != '[' {
-                            self.error(format!("Expected ']' for '[', but got {} instead!", popped));
-                        }
-                    }
-                    _ => {}
-                }
+            else if next == LexerTokenType::Operator(")".to_string()) {
+                break 'args;
+            }
+            else {
+                return Err(self.error("Syntax error".to_string()));
             }
-            
-            out_tks.push_back(token);
         }
-        return out_tks;
-    }
-
-    /**
-     * Also evaluated
-     */
-    fn eat_expr(&mut self, terminator: Vec<LexerTokenType>) -> Vec<ParserToken> {
-        let expr = self.eat_until(terminator);
-        let mut parse = Parser::new(expr, true);
-        let mut parsed = parse.parse_expression();
-        let evaluated = AstExpr::evaluate(&mut parsed);
-        return evaluated;
+        Ok(args)
     }
 
     fn peek(&self) -> Option<&LexerToken> {
@@ -566,24 +1004,38 @@ impl Parser {
         self.input.front()
     }
 
-    fn eat_checked(&mut self) -> LexerToken {
+    fn peek_is(&self, expect: LexerTokenType) -> bool {
+        if let Some(tk) = self.peek() {
+            return tk.token == expect;
+        }
+        false
+    }
+
+    fn eat_checked(&mut self) -> Result<LexerToken, GiffiError> {
         let popped = self.eat();
         if popped.is_none() {
-            self.error("Got unexpected EOF".to_string());
+            return Err(self.error("Got unexpected EOF".to_string()));
         }
-        popped.unwrap()
+        Ok(popped.unwrap())
     }
 
-    fn eat_expect(&mut self, expect: LexerTokenType) -> LexerToken {
+    fn eat_expect(&mut self, expect: LexerTokenType) -> Result<LexerToken, GiffiError> {
         let popped = self.eat();
         if popped.is_none() {
-            self.error(format!("Expected {:?} got EOF instead!", expect));
+            return Err(self.error(format!("Expected {:?} got EOF instead!", expect)));
         }
         let tk = popped.unwrap();
         if tk.token != expect {
-            self.error(format!("Expected {:?} got {:?} instead! :(", expect, tk.token));
+            return Err(self.error(format!("Expected {:?} got {:?} instead! :(", expect, tk.token)));
         }
-        tk
+        Ok(tk)
+    }
+
+    fn eat_identifier(&mut self, error_msg: &str) -> Result<String, GiffiError> {
+        if let LexerTokenType::Identifier(identifier) = self.eat_checked()?.token {
+            return Ok(identifier);
+        }
+        Err(self.error(error_msg.to_string()))
     }
 
     fn eat(&mut self) -> Option<LexerToken> {
@@ -596,19 +1048,122 @@ impl Parser {
         popped
     }
 
-    fn error(&self, msg: String) -> ! {
-        use colored::Colorize;
+    fn error(&self, msg: String) -> GiffiError {
         // TODO: Filename
-        println!("{}", format!("An error occurred while parsing at {}:{} '{}'", self.last_line, self.last_column, msg).red());
-        panic!("");
+        GiffiError::new(ErrorKind::Parse, msg, Span::new(self.last_line, self.last_column))
     }
 
-    fn new(tks: VecDeque<LexerToken>, is_expr: bool) -> Parser { 
+    fn new(tks: VecDeque<LexerToken>) -> Parser {
         Parser {
             input: tks,
-            is_expr: is_expr,
             last_column: 0,
-            last_line: 0
+            last_line: 0,
+            no_struct_literal: false,
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::ast::{Stmt, StmtKind, Expr, ExprKind};
+    use crate::lexer::{Lexer};
+    use crate::value::Value;
+    use super::Parser;
+
+    fn parse_expr(code: &str) -> Expr {
+        let mut stmts = Parser::parse(Lexer::lex(format!("{};", code)).unwrap()).unwrap();
+        assert_eq!(stmts.len(), 1);
+        if let StmtKind::Expr(expr) = stmts.pop().unwrap().kind {
+            return expr;
+        }
+        panic!("expected an expression statement");
+    }
+
+    /**
+     * Turns the tree back into a fully parenthesized string, "1+2*3" -> "(1+(2*3))"
+     */
+    fn to_string(expr: &Expr) -> String {
+        match &expr.kind {
+            ExprKind::Value(v) => v.to_string(),
+            ExprKind::Variable(v) => v.clone(),
+            ExprKind::Binary(lhs, op, rhs) => format!("({}{}{})", to_string(lhs), op, to_string(rhs)),
+            ExprKind::Unary(op, operand) => format!("({}{})", op, to_string(operand)),
+            ExprKind::Range(start, end, inclusive) => format!("({}{}{})",
+                start.as_ref().map(|start| to_string(start)).unwrap_or_default(),
+                if *inclusive { "..=" } else { ".." },
+                end.as_ref().map(|end| to_string(end)).unwrap_or_default()),
+            _ => panic!("not supported by the test"),
+        }
+    }
+
+    #[test]
+    fn test_operator_precedence() {
+        assert_eq!(to_string(&parse_expr("1+2*3")), "(1+(2*3))");
+        assert_eq!(to_string(&parse_expr("8/4/2")), "((8/4)/2)");
+        assert_eq!(to_string(&parse_expr("(1+2)*3")), "((1+2)*3)");
+        assert_eq!(to_string(&parse_expr("a < b && b + 1 == c")), "((a<b)&&((b+1)==c))");
+        assert_eq!(to_string(&parse_expr("-a * b")), "((-a)*b)");
+        assert_eq!(to_string(&parse_expr("!(a == b)")), "(!(a==b))");
+        assert_eq!(to_string(&parse_expr("--x")), "(-(-x))");
+        assert_eq!(to_string(&parse_expr("a -1")), "(a-1)");
+        assert_eq!(to_string(&parse_expr("a <= b + 1 && b >= c")), "((a<=(b+1))&&(b>=c))");
+        assert_eq!(to_string(&parse_expr("r << 16 | g << 8 | b")), "(((r<<16)|(g<<8))|b)");
+        assert_eq!(to_string(&parse_expr("a | b ^ c & d")), "(a|(b^(c&d)))");
+        assert_eq!(to_string(&parse_expr("1 << 2 + 3 < x == y")), "(((1<<(2+3))<x)==y)");
+        assert_eq!(to_string(&parse_expr("2 ** 3 ** 2")), "(2**(3**2))");
+        assert_eq!(to_string(&parse_expr("a || b && c || d")), "((a||(b&&c))||d)");
+        assert_eq!(to_string(&parse_expr("-2 ** 2 * 3")), "((-(2**2))*3)");
+        assert_eq!(to_string(&parse_expr("2 ** -x ** y")), "(2**(-(x**y)))");
+        assert_eq!(to_string(&parse_expr("a + 1..b * 2")), "((a+1)..(b*2))");
+        assert_eq!(to_string(&parse_expr("0..=n || m")), "(0..=(n||m))");
+        assert_eq!(to_string(&parse_expr("..-1")), "(..(-1))");
+        assert_eq!(to_string(&parse_expr("1..")), "(1..)");
+    }
+
+    #[test]
+    fn test_postfix_chains() {
+        // grid[y][x] -> Index(Index(grid, y), x)
+        let ExprKind::Index(inner, x) = parse_expr("grid[y][x]").kind else { panic!("expected an index") };
+        assert_eq!(x.kind, ExprKind::Variable("x".to_string()));
+        let ExprKind::Index(grid, y) = inner.kind else { panic!("expected a nested index") };
+        assert_eq!(grid.kind, ExprKind::Variable("grid".to_string()));
+        assert_eq!(y.kind, ExprKind::Variable("y".to_string()));
+
+        // f()[0].x
+        let ExprKind::Field(indexed, field) = parse_expr("f()[0].x").kind else { panic!("expected a field") };
+        assert_eq!(field, "x");
+        let ExprKind::Index(call, _) = indexed.kind else { panic!("expected an index") };
+        assert!(matches!(call.kind, ExprKind::Call(..)));
+    }
+
+    #[test]
+    fn test_nested_statements() {
+        let stmts = Parser::parse(Lexer::lex(String::from("
+        fn foo(a, b) {
+            while a {
+                if b { break; } else if a { continue; }
+            }
+        }
+        ")).unwrap()).unwrap();
+        assert_eq!(stmts.len(), 1);
+
+        let StmtKind::Function(name, args, body) = &stmts[0].kind else { panic!("expected a function") };
+        assert_eq!(name, "foo");
+        assert_eq!(args, &vec!["a".to_string(), "b".to_string()]);
+        let StmtKind::While(_, while_body) = &body[0].kind else { panic!("expected a while loop") };
+        let StmtKind::If(_, if_body, else_body) = &while_body[0].kind else { panic!("expected an if statement") };
+        assert_eq!(if_body[0].kind, StmtKind::Break);
+        let StmtKind::If(_, else_if_body, _) = &else_body[0].kind else { panic!("expected an else if") };
+        assert_eq!(else_if_body[0].kind, StmtKind::Continue);
+    }
+
+    #[test]
+    fn test_spans() {
+        let stmts = Parser::parse(Lexer::lex(String::from("let x = 1;\nlet y = x;")).unwrap()).unwrap();
+        assert_eq!(stmts[0].span.line, 1);
+        assert_eq!(stmts[1].span.line, 2);
+        let StmtKind::Let(_, value) = &stmts[1].kind else { panic!("expected a let statement") };
+        assert_eq!(value.kind, ExprKind::Variable("x".to_string()));
+        assert_eq!(value.span.line, 2);
+    }
+}
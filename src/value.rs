@@ -1,24 +1,453 @@
 
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::bytecode::FunctionProto;
+use crate::interpreter::NativeFunction;
+
 #[derive(Debug)]
 pub enum ValueE {
     ParsingError,
     TypeMismatch,
     UnkownOperation,
     DivisionByZero,
+    Overflow,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Clone)]
 pub enum Value {
     Int(i64),
     Float(f64),
     Literal(String),
+    Char(char),
     Boolean(bool),
-    Array(Vec<Value>),
+    Array(Rc<RefCell<Vec<Value>>>), // Arrays, maps, objects and bytes are shared by reference
+    Bytes(Rc<RefCell<Vec<u8>>>),    // Binary data, e.g. file contents or pixels
+    Map(Rc<RefCell<Map>>),
+    Struct(Rc<StructDef>),
+    Object(Rc<RefCell<Object>>),
     Ptr(*mut u32),
+    Function(Function),
+    Range(Box<Range>), // Boxed, like the name of natives, to keep values small for the VM's stack
     Null,
 }
 
+/**
+ * Arrays, maps and objects are compared by their contents, unless they're the same reference.
+ */
+impl PartialEq for Value {
+    fn eq(&self, other: &Value) -> bool {
+        self.eq_with(other, &mut vec![])
+    }
+}
+
+impl Value {
+    /**
+     * Address of an array, map or object, which can contain other values and so themselves.
+     */
+    fn reference_key(&self) -> Option<usize> {
+        match self {
+            Value::Array(array) => Some(Rc::as_ptr(array) as usize),
+            Value::Map(map) => Some(Rc::as_ptr(map) as usize),
+            Value::Object(object) => Some(Rc::as_ptr(object) as usize),
+            _ => None,
+        }
+    }
+
+    /**
+     * "seen" holds the pairs of arrays, maps and objects which are already being compared,
+     * meeting one of them again means the values contain themselves, which counts as equal.
+     */
+    fn eq_with(&self, other: &Value, seen: &mut Vec<(usize, usize)>) -> bool {
+        if let (Some(a), Some(b)) = (self.reference_key(), other.reference_key()) {
+            let pair = (a, b);
+            if pair.0 == pair.1 || seen.contains(&pair) {
+                return true;
+            }
+            seen.push(pair);
+        }
+
+        match (self, other) {
+            (Value::Array(a), Value::Array(b)) => {
+                let (a, b) = (a.borrow(), b.borrow());
+                return a.len() == b.len() && a.iter().zip(b.iter()).all(|(lhs, rhs)| lhs.eq_with(rhs, seen));
+            }
+            (Value::Map(a), Value::Map(b)) => {
+                let (a, b) = (a.borrow(), b.borrow());
+                return a.len() == b.len() && a.iter().all(|(key, value)| b.get(key).is_some_and(|other| value.eq_with(other, seen)));
+            }
+            (Value::Object(a), Value::Object(b)) => {
+                let (a, b) = (a.borrow(), b.borrow());
+                return Rc::ptr_eq(&a.def, &b.def) && a.fields.iter().zip(b.fields.iter()).all(|(lhs, rhs)| lhs.eq_with(rhs, seen));
+            }
+            (Value::Int(a), Value::Int(b)) => { return a == b; }
+            (Value::Float(a), Value::Float(b)) => { return a == b; }
+            (Value::Literal(a), Value::Literal(b)) => { return a == b; }
+            (Value::Char(a), Value::Char(b)) => { return a == b; }
+            (Value::Boolean(a), Value::Boolean(b)) => { return a == b; }
+            (Value::Bytes(a), Value::Bytes(b)) => { return a == b; }
+            (Value::Struct(a), Value::Struct(b)) => { return Rc::ptr_eq(a, b); }
+            (Value::Ptr(a), Value::Ptr(b)) => { return a == b; }
+            (Value::Function(a), Value::Function(b)) => { return a == b; }
+            (Value::Range(a), Value::Range(b)) => { return a == b; }
+            (Value::Null, Value::Null) => { return true; }
+            _ => { return false; }
+        }
+    }
+}
+
+/**
+ * String keyed map, iterating it follows the order in which the keys were inserted.
+ * Maps with the same keys and values are equal regardless of the order.
+ */
+#[derive(Debug, Clone, Default)]
+pub struct Map {
+    entries: Vec<(String, Value)>,
+    indices: HashMap<String, usize>, // Key -> position in entries
+}
+
+impl Map {
+    pub fn new() -> Map {
+        Map::default()
+    }
+
+    pub fn get(&self, key: &String) -> Option<&Value> {
+        if let Some(idx) = self.indices.get(key) {
+            return Some(&self.entries[*idx].1);
+        }
+        None
+    }
+
+    /**
+     * Overwrites the value of an existing key, new keys are added to the end.
+     */
+    pub fn insert(&mut self, key: String, value: Value) {
+        if let Some(idx) = self.indices.get(&key) {
+            self.entries[*idx].1 = value;
+            return;
+        }
+        self.indices.insert(key.clone(), self.entries.len());
+        self.entries.push((key, value));
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn keys(&self) -> Vec<String> {
+        self.entries.iter().map(|(key, _)| key.clone()).collect()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, (String, Value)> {
+        self.entries.iter()
+    }
+}
+
+impl PartialEq for Map {
+    fn eq(&self, other: &Map) -> bool {
+        if self.len() != other.len() {
+            return false;
+        }
+        for (key, value) in self.iter() {
+            if other.get(key) != Some(value) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/**
+ * Declared with "struct Point { x, y }", the methods get added by "impl" blocks.
+ * Every decleration is a distinct type, even if the names are the same.
+ */
+#[derive(Debug)]
+pub struct StructDef {
+    pub name: String,
+    pub fields: Vec<String>,
+    pub methods: RefCell<HashMap<String, Function>>,
+}
+
+/**
+ * "start..end" or "start..=end", missing ends are None.
+ * Negative ends count from the back when slicing.
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Range {
+    pub start: Option<i64>,
+    pub end: Option<i64>,
+    pub inclusive: bool,
+}
+
+impl Range {
+    /**
+     * Start and (exclusive) end of the slice of a sequence with "len" elements, None if it's out of bounds.
+     */
+    pub fn slice_bounds(&self, len: usize) -> Option<(usize, usize)> {
+        let start = Range::resolve(self.start.unwrap_or(0), len)?;
+        let mut end = match self.end {
+            Some(end) => Range::resolve(end, len)?,
+            None => len,
+        };
+        if self.inclusive {
+            end += 1;
+        }
+        if start > end || end > len {
+            return None;
+        }
+        Some((start, end))
+    }
+
+    /**
+     * Negative indices count from the end, "-1" is the last element.
+     */
+    pub fn resolve(idx: i64, len: usize) -> Option<usize> {
+        let resolved = if idx < 0 { idx + len as i64 } else { idx };
+        if resolved < 0 {
+            return None;
+        }
+        Some(resolved as usize)
+    }
+
+    /**
+     * Number at "position" when iterating over the range, None after the end.
+     */
+    pub fn nth(&self, position: usize) -> Option<i64> {
+        let value = self.start?.checked_add(position as i64)?;
+        match self.end {
+            Some(end) if value > end || (value == end && !self.inclusive) => None,
+            _ => Some(value),
+        }
+    }
+}
+
+/**
+ * Instance of a struct, the fields are in the order of the decleration.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Object {
+    pub def: Rc<StructDef>,
+    pub fields: Vec<Value>,
+}
+
+impl StructDef {
+    pub fn field_index(&self, field: &String) -> Option<usize> {
+        self.fields.iter().position(|f| f == field)
+    }
+}
+
+impl PartialEq for StructDef {
+    fn eq(&self, other: &StructDef) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl Object {
+    pub fn get(&self, field: &String) -> Option<&Value> {
+        let idx = self.def.field_index(field)?;
+        Some(&self.fields[idx])
+    }
+}
+
+/**
+ * Functions are values, they can be stored in variables and passed around.
+ * Two functions are only equal when they're the same closure (or the same native).
+ */
+#[derive(Clone)]
+pub enum Function {
+    Script(Rc<Closure>),
+    Native(Rc<str>, u32, NativeFunction), // Name, argument count
+}
+
+/**
+ * A compiled function together with the variables it captured from the enclosing functions.
+ */
+pub struct Closure {
+    pub proto: Rc<FunctionProto>,
+    pub upvalues: Vec<Rc<RefCell<Upvalue>>>,
+}
+
+/**
+ * Captured variable, points into the stack while the variable is still alive
+ * and holds the value itself once the variable's scope has ended.
+ */
+#[derive(Debug)]
+pub enum Upvalue {
+    Open(usize),
+    Closed(Value),
+}
+
+impl Function {
+    pub fn name(&self) -> String {
+        match self {
+            Function::Script(closure) => { return closure.proto.name.clone(); }
+            Function::Native(name, _, _) => { return name.to_string(); }
+        }
+    }
+}
+
+impl PartialEq for Function {
+    fn eq(&self, other: &Function) -> bool {
+        match (self, other) {
+            (Function::Script(a), Function::Script(b)) => { return Rc::ptr_eq(a, b); }
+            (Function::Native(a, _, _), Function::Native(b, _, _)) => { return a == b; }
+            _ => { return false; }
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.format(&mut vec![]))
+    }
+}
+
+impl fmt::Debug for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.debug_format(&mut vec![]))
+    }
+}
+
+impl Value {
+    /**
+     * Like the derived Debug, but arrays, maps and objects which contain themselves are printed as "[...]" the second time.
+     */
+    fn debug_format(&self, seen: &mut Vec<usize>) -> String {
+        let key = self.reference_key();
+        if let Some(key) = key {
+            if seen.contains(&key) {
+                return "[...]".to_string();
+            }
+            seen.push(key);
+        }
+        let string = match self {
+            Value::Int(i) => format!("Int({})", i),
+            Value::Float(f) => format!("Float({:?})", f),
+            Value::Literal(s) => format!("Literal({:?})", s),
+            Value::Char(c) => format!("Char({:?})", c),
+            Value::Boolean(b) => format!("Boolean({})", b),
+            Value::Array(array) => {
+                let elements: Vec<String> = array.borrow().iter().map(|element| element.debug_format(seen)).collect();
+                format!("Array([{}])", elements.join(", "))
+            }
+            Value::Bytes(bytes) => format!("Bytes({:?})", bytes.borrow()),
+            Value::Map(map) => {
+                let entries: Vec<String> = map.borrow().iter().map(|(key, value)| format!("{:?}: {}", key, value.debug_format(seen))).collect();
+                format!("Map({{{}}})", entries.join(", "))
+            }
+            Value::Struct(def) => format!("Struct({})", def.name),
+            Value::Object(object) => {
+                let object = object.borrow();
+                let fields: Vec<String> = object.def.fields.iter().zip(object.fields.iter())
+                    .map(|(field, value)| format!("{}: {}", field, value.debug_format(seen))).collect();
+                format!("Object({} {{ {} }})", object.def.name, fields.join(", "))
+            }
+            Value::Ptr(ptr) => format!("Ptr({:?})", ptr),
+            Value::Function(function) => format!("Function({:?})", function),
+            Value::Range(range) => format!("Range({:?})", range),
+            Value::Null => "Null".to_string(),
+        };
+        if key.is_some() {
+            seen.pop();
+        }
+        string
+    }
+}
+
+impl fmt::Debug for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Closures can capture themselves, so only the name gets printed
+        write!(f, "<fn {}>", self.name())
+    }
+}
+
 impl Value {
+    pub fn array(array: Vec<Value>) -> Value {
+        Value::Array(Rc::new(RefCell::new(array)))
+    }
+
+    pub fn map(map: Map) -> Value {
+        Value::Map(Rc::new(RefCell::new(map)))
+    }
+
+    pub fn object(object: Object) -> Value {
+        Value::Object(Rc::new(RefCell::new(object)))
+    }
+
+    pub fn bytes(bytes: Vec<u8>) -> Value {
+        Value::Bytes(Rc::new(RefCell::new(bytes)))
+    }
+
+    /**
+     * Duplicates arrays, maps, objects and bytes including everything inside of them,
+     * values referenced multiple times (or cyclicly) are copied once.
+     * Functions and struct types are shared with the original.
+     */
+    pub fn deep_copy(&self) -> Value {
+        let mut copies = HashMap::new();
+        self.deep_copy_with(&mut copies)
+    }
+
+    fn deep_copy_with(&self, copies: &mut HashMap<usize, Value>) -> Value {
+        match self {
+            Value::Array(array) => {
+                let key = Rc::as_ptr(array) as usize;
+                if let Some(copy) = copies.get(&key) {
+                    return copy.clone();
+                }
+                let copy = Rc::new(RefCell::new(vec![]));
+                copies.insert(key, Value::Array(copy.clone()));
+                let elements: Vec<Value> = array.borrow().iter().map(|element| element.deep_copy_with(copies)).collect();
+                *copy.borrow_mut() = elements;
+                return Value::Array(copy);
+            }
+            Value::Map(map) => {
+                let key = Rc::as_ptr(map) as usize;
+                if let Some(copy) = copies.get(&key) {
+                    return copy.clone();
+                }
+                let copy = Rc::new(RefCell::new(Map::new()));
+                copies.insert(key, Value::Map(copy.clone()));
+                let entries: Vec<(String, Value)> = map.borrow().iter().map(|(k, v)| (k.clone(), v.deep_copy_with(copies))).collect();
+                for (k, v) in entries {
+                    copy.borrow_mut().insert(k, v);
+                }
+                return Value::Map(copy);
+            }
+            Value::Object(object) => {
+                let key = Rc::as_ptr(object) as usize;
+                if let Some(copy) = copies.get(&key) {
+                    return copy.clone();
+                }
+                let def = object.borrow().def.clone();
+                let copy = Rc::new(RefCell::new(Object { def: def, fields: vec![] }));
+                copies.insert(key, Value::Object(copy.clone()));
+                let fields: Vec<Value> = object.borrow().fields.iter().map(|field| field.deep_copy_with(copies)).collect();
+                copy.borrow_mut().fields = fields;
+                return Value::Object(copy);
+            }
+            Value::Bytes(bytes) => {
+                let key = Rc::as_ptr(bytes) as usize;
+                if let Some(copy) = copies.get(&key) {
+                    return copy.clone();
+                }
+                let copy = Value::bytes(bytes.borrow().clone());
+                copies.insert(key, copy.clone());
+                return copy;
+            }
+            _ => {
+                return self.clone();
+            }
+        }
+    }
+
     /**
      * Parses Ints, Floats, Bools, Null, etc.
      * Doesn't parse string literals!
@@ -50,6 +479,9 @@ impl Value {
             Value::Literal(literal) => {
                 return !literal.is_empty();
             }
+            Value::Char(c) => {
+                return c != &'\0';
+            }
             Value::Boolean(value) => {
                 return value.clone();
             }
@@ -59,62 +491,142 @@ impl Value {
             Value::Ptr(ptr) => {
                 return !ptr.is_null();
             }
-            _ => {
-                panic!("Not implemented yet!");
-            }
-        }
-    }
-
-    pub fn do_operation(&self, op: &String, other: Value) -> Result<Value, ValueE> {
-        match op.as_str() {
-            "+" => {
-                return self.add(other);
+            Value::Float(value) => {
+                return value != &0.0;
             }
-            "-" => {
-                return self.sub(other);
+            Value::Array(array) => {
+                return !array.borrow().is_empty();
             }
-            "*" => {
-                return self.mul(other);
+            Value::Bytes(bytes) => {
+                return !bytes.borrow().is_empty();
             }
-            "/" => {
-                return self.div(other);
+            Value::Map(map) => {
+                return !map.borrow().is_empty();
             }
-            "%" => {
-                return self.modulo(other);
+            Value::Struct(_) | Value::Object(_) => {
+                return true;
             }
-            "<" => {
-                return self.less_than(other);
+            Value::Function(_) | Value::Range(_) => {
+                return true;
             }
-            ">" => {
-                return self.greater_than(other);
+        }
+    }
+
+    /**
+     * "lhs ** rhs", Ints with a negative exponent give a Float.
+     * Results which don't fit into an Int or a Float are an error instead of wrapping around.
+     */
+    pub fn pow(&self, rhs: &Value) -> Result<Value, ValueE> {
+        match (self, rhs) {
+            (Value::Int(base), Value::Int(exp)) if *exp >= 0 => {
+                let exp = u32::try_from(*exp).map_err(|_| ValueE::Overflow)?;
+                return base.checked_pow(exp).map(Value::Int).ok_or(ValueE::Overflow);
             }
-            "==" => {
-                return Ok(Value::Boolean(*self == other));
-            },
-            "!=" => {
-                return Ok(Value::Boolean(*self != other));
+            (Value::Int(_) | Value::Float(_), Value::Int(_) | Value::Float(_)) => {
+                let (base, exp) = (self.as_f64(), rhs.as_f64());
+                let r = base.powf(exp);
+                if r.is_infinite() && base.is_finite() && exp.is_finite() {
+                    return Err(ValueE::Overflow);
+                }
+                return Ok(Value::Float(r));
             }
-            "&&" => {
-                return Ok(Value::Boolean(self.is_true() && other.is_true()));
-            },
-            "||" => {
-                return Ok(Value::Boolean(self.is_true() || other.is_true()));
+            _ => { return Err(ValueE::TypeMismatch); }
+        }
+    }
+
+    fn as_f64(&self) -> f64 {
+        match self {
+            Value::Int(i) => { return *i as f64; }
+            Value::Float(f) => { return *f; }
+            _ => { return f64::NAN; }
+        }
+    }
+
+    /**
+     * Ordering used by the comparison operators. Numbers are compared by value, strings lexicographically, chars by their code point
+     * and arrays element by element, an array which is the start of a longer one is smaller.
+     * None when there is no order, e.g. NaN.
+     */
+    pub fn compare(&self, other: &Value) -> Result<Option<Ordering>, ValueE> {
+        self.compare_with(other, &mut vec![])
+    }
+
+    /**
+     * Arrays which are already being compared (they contain themselves) count as equal, like in "eq_with".
+     */
+    fn compare_with(&self, other: &Value, seen: &mut Vec<(usize, usize)>) -> Result<Option<Ordering>, ValueE> {
+        match (self, other) {
+            (Value::Int(a), Value::Int(b)) => { return Ok(Some(a.cmp(b))); }
+            (Value::Int(a), Value::Float(b)) => { return Ok((*a as f64).partial_cmp(b)); }
+            (Value::Float(a), Value::Int(b)) => { return Ok(a.partial_cmp(&(*b as f64))); }
+            (Value::Float(a), Value::Float(b)) => { return Ok(a.partial_cmp(b)); }
+            (Value::Literal(a), Value::Literal(b)) => { return Ok(Some(a.cmp(b))); }
+            (Value::Char(a), Value::Char(b)) => { return Ok(Some(a.cmp(b))); }
+            (Value::Array(a), Value::Array(b)) => {
+                let pair = (Rc::as_ptr(a) as usize, Rc::as_ptr(b) as usize);
+                if pair.0 == pair.1 || seen.contains(&pair) {
+                    return Ok(Some(Ordering::Equal));
+                }
+                seen.push(pair);
+                let (a, b) = (a.borrow(), b.borrow());
+                for (lhs, rhs) in a.iter().zip(b.iter()) {
+                    let ordering = lhs.compare_with(rhs, seen)?;
+                    if ordering != Some(Ordering::Equal) {
+                        return Ok(ordering);
+                    }
+                }
+                return Ok(Some(a.len().cmp(&b.len())));
             }
-            _ => {
-                panic!("Invalid operation");
+            _ => { return Err(ValueE::TypeMismatch); }
+        }
+    }
+
+    /**
+     * "seen" holds the arrays, maps and objects being printed, one which contains itself is printed as "[...]" the second time.
+     */
+    fn format(&self, seen: &mut Vec<usize>) -> String {
+        let key = self.reference_key();
+        if let Some(key) = key {
+            if seen.contains(&key) {
+                return "[...]".to_string();
             }
+            seen.push(key);
         }
+        let string = self.format_inner(seen);
+        if key.is_some() {
+            seen.pop();
+        }
+        string
     }
 
-    pub fn to_string(&self) -> String {
+    fn format_inner(&self, seen: &mut Vec<usize>) -> String {
         match self {
             Value::Int(i) => { return i.to_string(); },
             Value::Float(f) => { return f.to_string(); },
             Value::Literal(s) => { return s.clone(); },
+            Value::Char(c) => { return c.to_string(); },
             Value::Boolean(b) => { return if *b { "true".to_string() } else { "false".to_string() } }
             Value::Null => { return "null".to_string(); },
+            Value::Range(range) => {
+                let start = range.start.map(|start| start.to_string()).unwrap_or_default();
+                let end = range.end.map(|end| end.to_string()).unwrap_or_default();
+                return format!("{}{}{}", start, if range.inclusive { "..=" } else { ".." }, end);
+            }
             Value::Ptr(ptr) => { return format!("{:?}", ptr); },
+            Value::Function(function) => { return format!("{:?}", function); },
+            Value::Struct(def) => { return format!("<struct {}>", def.name); },
+            Value::Object(object) => {
+                let object = object.borrow();
+                let fields: Vec<String> = object.def.fields.iter().zip(object.fields.iter())
+                    .map(|(field, value)| format!("{}:{}", field, value.format(seen))).collect();
+                return format!("{}{{{}}}", object.def.name, fields.join(","));
+            }
+            Value::Map(map) => {
+                let entries: Vec<String> = map.borrow().iter().map(|(key, value)| format!("{}:{}", key, value.format(seen))).collect();
+                return format!("{{{}}}", entries.join(","));
+            }
             Value::Array(array) => {
+                let array = array.borrow();
                 if array.len() == 0 {
                     return "[]".to_string();
                 }
@@ -123,9 +635,9 @@ impl Value {
                 let mut iter = array.iter().peekable();
                 loop {
                     let element = iter.next().expect("this should be quaranteed be valid");
-                    str += &element.to_string();
+                    str += &element.format(seen);
 
-                    if let None = iter.peek() {
+                    if iter.peek().is_none() {
                         break;
                     }
                     str.push(',');
@@ -133,6 +645,32 @@ impl Value {
                 str.push(']');
                 return str;
             }
+            Value::Bytes(bytes) => {
+                let bytes: Vec<String> = bytes.borrow().iter().map(|byte| byte.to_string()).collect();
+                return format!("b[{}]", bytes.join(","));
+            }
+        }
+    }
+
+    /**
+     * Name of the value's type for error messages
+     */
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Int(_) => "Int",
+            Value::Float(_) => "Float",
+            Value::Literal(_) => "String",
+            Value::Char(_) => "Char",
+            Value::Boolean(_) => "Bool",
+            Value::Array(_) => "Array",
+            Value::Bytes(_) => "Bytes",
+            Value::Map(_) => "Map",
+            Value::Struct(_) => "Struct",
+            Value::Object(_) => "Object",
+            Value::Ptr(_) => "Pointer",
+            Value::Function(_) => "Function",
+            Value::Range(_) => "Range",
+            Value::Null => "Null",
         }
     }
 
@@ -170,8 +708,19 @@ pub trait ValueAdder<T> {
     fn sub(&self, rhs: T) -> Result<Value, ValueE>;
     fn mul(&self, rhs: T) -> Result<Value, ValueE>;
     fn div(&self, rhs: T) -> Result<Value, ValueE>;
-    fn less_than(&self, rhs: T) -> Result<Value, ValueE>;
-    fn greater_than(&self, rhs: T) -> Result<Value, ValueE>;
+    // Only Values can be compared with each other, see Value::compare
+    fn less_than(&self, _rhs: T) -> Result<Value, ValueE> {
+        Err(ValueE::TypeMismatch)
+    }
+    fn greater_than(&self, _rhs: T) -> Result<Value, ValueE> {
+        Err(ValueE::TypeMismatch)
+    }
+    fn less_equal(&self, _rhs: T) -> Result<Value, ValueE> {
+        Err(ValueE::TypeMismatch)
+    }
+    fn greater_equal(&self, _rhs: T) -> Result<Value, ValueE> {
+        Err(ValueE::TypeMismatch)
+    }
     fn modulo(&self, rhs: T) -> Result<Value, ValueE>;
 }
 
@@ -187,6 +736,9 @@ impl ValueAdder<Value> for Value {
         if let Value::Literal(value) = rhs {
             return self.add(value);
         }
+        if let Value::Char(value) = rhs {
+            return self.add(value.to_string());
+        }
         return Err(ValueE::TypeMismatch);
     }
     
@@ -230,29 +782,19 @@ impl ValueAdder<Value> for Value {
     }
 
     fn less_than(&self, rhs: Value) -> Result<Value, ValueE> {
-        if let Value::Int(value) = rhs {
-            return self.less_than(value);
-        }
-        if let Value::Float(value) = rhs {
-            return self.less_than(value);
-        }
-        if let Value::Literal(value) = rhs {
-            return self.less_than(value);
-        }
-        return Err(ValueE::TypeMismatch);
+        return Ok(Value::Boolean(self.compare(&rhs)? == Some(Ordering::Less)));
     }
 
     fn greater_than(&self, rhs: Value) -> Result<Value, ValueE> {
-        if let Value::Int(value) = rhs {
-            return self.greater_than(value);
-        }
-        if let Value::Float(value) = rhs {
-            return self.greater_than(value);
-        }
-        if let Value::Literal(value) = rhs {
-            return self.greater_than(value);
-        }
-        return Err(ValueE::TypeMismatch);
+        return Ok(Value::Boolean(self.compare(&rhs)? == Some(Ordering::Greater)));
+    }
+
+    fn less_equal(&self, rhs: Value) -> Result<Value, ValueE> {
+        return Ok(Value::Boolean(matches!(self.compare(&rhs)?, Some(Ordering::Less | Ordering::Equal))));
+    }
+
+    fn greater_equal(&self, rhs: Value) -> Result<Value, ValueE> {
+        return Ok(Value::Boolean(matches!(self.compare(&rhs)?, Some(Ordering::Greater | Ordering::Equal))));
     }
 
     fn modulo(&self, rhs: Value) -> Result<Value, ValueE> {
@@ -273,7 +815,7 @@ impl ValueAdder<Value> for Value {
 impl ValueAdder<i64> for Value {
     fn add(&self, rhs: i64) -> Result<Value, ValueE> {
         if let Value::Int(lhs) = self {
-            return Ok(Value::Int(lhs.clone() + rhs));
+            return lhs.checked_add(rhs).map(Value::Int).ok_or(ValueE::Overflow);
         }
         if let Value::Float(lhs) = self {
             return Ok(Value::Float(lhs.clone() + (rhs as f64)));
@@ -283,7 +825,7 @@ impl ValueAdder<i64> for Value {
     
     fn sub(&self, rhs: i64) -> Result<Value, ValueE> {
         if let Value::Int(lhs) = self {
-            return Ok(Value::Int(lhs.clone() - rhs));
+            return lhs.checked_sub(rhs).map(Value::Int).ok_or(ValueE::Overflow);
         }
         if let Value::Float(lhs) = self {
             return Ok(Value::Float(lhs.clone() - (rhs as f64)));
@@ -293,7 +835,7 @@ impl ValueAdder<i64> for Value {
 
     fn mul(&self, rhs: i64) -> Result<Value, ValueE> {
         if let Value::Int(lhs) = self {
-            return Ok(Value::Int(lhs.clone() * rhs));
+            return lhs.checked_mul(rhs).map(Value::Int).ok_or(ValueE::Overflow);
         }
         if let Value::Float(lhs) = self {
             return Ok(Value::Float(lhs.clone() * (rhs as f64)));
@@ -306,7 +848,7 @@ impl ValueAdder<i64> for Value {
             if rhs == 0 {
                 return Err(ValueE::DivisionByZero);
             }
-            return Ok(Value::Int(lhs.clone() / rhs));
+            return lhs.checked_div(rhs).map(Value::Int).ok_or(ValueE::Overflow);
         }
         if let Value::Float(lhs) = self {
             if rhs == 0 {
@@ -317,32 +859,12 @@ impl ValueAdder<i64> for Value {
         return Err(ValueE::TypeMismatch);
     }
 
-    fn less_than(&self, rhs: i64) -> Result<Value, ValueE> {
-        if let Value::Int(lhs) = self {
-            return Ok(Value::Boolean(lhs.clone() < rhs));
-        }
-        if let Value::Float(lhs) = self {
-            return Ok(Value::Boolean(lhs.clone() < (rhs as f64)));
-        }
-        return Err(ValueE::TypeMismatch);
-    }
-
-    fn greater_than(&self, rhs: i64) -> Result<Value, ValueE> {
-        if let Value::Int(lhs) = self {
-            return Ok(Value::Boolean(lhs.clone() > rhs));
-        }
-        if let Value::Float(lhs) = self {
-            return Ok(Value::Boolean(lhs.clone() > (rhs as f64)));
-        }
-        return Err(ValueE::TypeMismatch);
-    }
-
     fn modulo(&self, rhs: i64) -> Result<Value, ValueE> {
         if let Value::Int(lhs) = self {
             if rhs == 0 {
                 return Err(ValueE::DivisionByZero);
             }
-            return Ok(Value::Int(lhs.clone() % rhs));
+            return lhs.checked_rem(rhs).map(Value::Int).ok_or(ValueE::Overflow);
         }
         if let Value::Float(lhs) = self {
             if rhs == 0 {
@@ -358,7 +880,7 @@ impl ValueAdder<i64> for Value {
 impl ValueAdder<f64> for Value {
     fn add(&self, rhs: f64) -> Result<Value, ValueE> {
         if let Value::Int(lhs) = self {
-            return Ok(Value::Int(lhs.clone() + (rhs as i64)));
+            return lhs.checked_add(rhs as i64).map(Value::Int).ok_or(ValueE::Overflow);
         }
         if let Value::Float(lhs) = self {
             return Ok(Value::Float(lhs.clone() + rhs));
@@ -368,7 +890,7 @@ impl ValueAdder<f64> for Value {
     
     fn sub(&self, rhs: f64) -> Result<Value, ValueE> {
         if let Value::Int(lhs) = self {
-            return Ok(Value::Int(lhs.clone() - (rhs as i64)));
+            return lhs.checked_sub(rhs as i64).map(Value::Int).ok_or(ValueE::Overflow);
         }
         if let Value::Float(lhs) = self {
             return Ok(Value::Float(lhs.clone() - rhs));
@@ -378,7 +900,7 @@ impl ValueAdder<f64> for Value {
 
     fn mul(&self, rhs: f64) -> Result<Value, ValueE> {
         if let Value::Int(lhs) = self {
-            return Ok(Value::Int(lhs.clone() * (rhs as i64)));
+            return lhs.checked_mul(rhs as i64).map(Value::Int).ok_or(ValueE::Overflow);
         }
         if let Value::Float(lhs) = self {
             return Ok(Value::Float(lhs.clone() * rhs));
@@ -388,10 +910,12 @@ impl ValueAdder<f64> for Value {
 
     fn div(&self, rhs: f64) -> Result<Value, ValueE> {
         if let Value::Int(lhs) = self {
-            if rhs == 0.0 {
+            // The divisor is truncated, "7 / 0.5" divides by 0
+            let rhs = rhs as i64;
+            if rhs == 0 {
                 return Err(ValueE::DivisionByZero);
             }
-            return Ok(Value::Int(lhs.clone() / (rhs as i64)));
+            return lhs.checked_div(rhs).map(Value::Int).ok_or(ValueE::Overflow);
         }
         if let Value::Float(lhs) = self {
             if rhs == 0.0 {
@@ -402,32 +926,13 @@ impl ValueAdder<f64> for Value {
         return Err(ValueE::TypeMismatch);
     }
 
-    fn less_than(&self, rhs: f64) -> Result<Value, ValueE> {
-        if let Value::Int(lhs) = self {
-            return Ok(Value::Boolean((lhs.clone() as f64) < rhs));
-        }
-        if let Value::Float(lhs) = self {
-            return Ok(Value::Boolean(lhs.clone() < rhs));
-        }
-        return Err(ValueE::TypeMismatch);
-    }
-
-    fn greater_than(&self, rhs: f64) -> Result<Value, ValueE> {
-        if let Value::Int(lhs) = self {
-            return Ok(Value::Boolean((lhs.clone() as f64) > rhs));
-        }
-        if let Value::Float(lhs) = self {
-            return Ok(Value::Boolean(lhs.clone() > rhs));
-        }
-        return Err(ValueE::TypeMismatch);
-    }
-
     fn modulo(&self, rhs: f64) -> Result<Value, ValueE> {
         if let Value::Int(lhs) = self {
-            if rhs == 0.0 {
+            let rhs = rhs as i64;
+            if rhs == 0 {
                 return Err(ValueE::DivisionByZero);
             }
-            return Ok(Value::Int(lhs.clone() % (rhs as i64)));
+            return lhs.checked_rem(rhs).map(Value::Int).ok_or(ValueE::Overflow);
         }
         if let Value::Float(lhs) = self {
             if rhs == 0.0 {
@@ -445,6 +950,9 @@ impl ValueAdder<String> for Value {
         if let Value::Literal(lhs) = self {
             return Ok(Value::Literal(lhs.clone() + &rhs));
         }
+        if let Value::Char(lhs) = self {
+            return Ok(Value::Literal(format!("{}{}", lhs, rhs)));
+        }
         return Err(ValueE::UnkownOperation);
     }
     
@@ -460,14 +968,6 @@ impl ValueAdder<String> for Value {
         return Err(ValueE::UnkownOperation);
     }
 
-    fn less_than(&self, _rhs: String) -> Result<Value, ValueE> {
-        return Err(ValueE::UnkownOperation);
-    }
-
-    fn greater_than(&self, _rhs: String) -> Result<Value, ValueE> {
-        return Err(ValueE::UnkownOperation);
-    }
-
     fn modulo(&self, _rhs: String) -> Result<Value, ValueE> {
         return Err(ValueE::UnkownOperation);
     }